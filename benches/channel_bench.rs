@@ -1,5 +1,6 @@
 use criterion::{Criterion, criterion_group, criterion_main};
 use integration_project::channel::*;
+use integration_project::mpmc_ring_buffer::MpmcRingBuffer;
 use tokio::task::JoinSet;
 
 use std::hint::black_box;
@@ -25,6 +26,44 @@ fn bench_send_fast_path(c: &mut Criterion) {
     });
 }
 
+// Send and receive on a long-lived channel with nobody parked, so every
+// wakeup finds an empty waiter queue
+fn bench_handoff_no_waiters(c: &mut Criterion) {
+    c.bench_function("handoff_no_waiters", |b| {
+        let (tx, rx) = channel::<u64>(64);
+
+        b.iter(|| {
+            tx.try_send(black_box(1)).unwrap();
+            black_box(rx.try_recv().unwrap());
+        });
+    });
+}
+
+// Same handoff through the non-cloneable halves
+fn bench_handoff_no_waiters_unshared(c: &mut Criterion) {
+    c.bench_function("handoff_no_waiters_unshared", |b| {
+        let (tx, rx) = channel_unshared::<u64>(64);
+
+        b.iter(|| {
+            tx.try_send(black_box(1)).unwrap();
+            black_box(rx.try_recv().unwrap());
+        });
+    });
+}
+
+// Same fast path, over the lock-free storage instead of `RingBuffer`
+fn bench_send_fast_path_lock_free(c: &mut Criterion) {
+    c.bench_function("send_no_backpressure_lock_free", |b| {
+        let rt = Runtime::new().unwrap();
+
+        b.to_async(&rt).iter(|| async {
+            let (tx, rx) = channel_with_buffer(MpmcRingBuffer::<String>::new(4));
+            tx.send("message".to_string()).await.unwrap();
+            black_box(rx.recv().await.unwrap());
+        });
+    });
+}
+
 // Benchmark 2: Backpressure path (blocking + unblock)
 fn bench_send_with_backpressure(c: &mut Criterion) {
     c.bench_function("send_with_backpressure", |b| {
@@ -159,13 +198,54 @@ fn bench_multi_threaded_mpsc(c: &mut Criterion) {
     });
 }
 
+// Round trips through an echo task on another worker, so each `recv` waits
+// on the other side; compares parking right away with spinning first
+fn bench_ping_pong(c: &mut Criterion, name: &str, spin_before_park: u32) {
+    c.bench_function(name, |b| {
+        let rt = Runtime::new().unwrap();
+        let config = ChannelConfig {
+            spin_before_park,
+            ..ChannelConfig::new(2)
+        };
+        let (ping_tx, ping_rx) = channel_with_config::<u64>(config);
+        let (pong_tx, pong_rx) = channel_with_config::<u64>(config);
+
+        rt.spawn(async move {
+            while let Some(value) = ping_rx.recv().await {
+                if pong_tx.send(value).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (ping_tx, pong_rx) = (&ping_tx, &pong_rx);
+        b.to_async(&rt).iter(|| async move {
+            ping_tx.send(1).await.unwrap();
+            black_box(pong_rx.recv().await.unwrap());
+        });
+    });
+}
+
+fn bench_ping_pong_park(c: &mut Criterion) {
+    bench_ping_pong(c, "ping_pong_park", 0);
+}
+
+fn bench_ping_pong_spin(c: &mut Criterion) {
+    bench_ping_pong(c, "ping_pong_spin", 100);
+}
+
 criterion_group!(
     benches,
     bench_send_fast_path_mpsc,
     bench_mpsc_backpressure,
     bench_send_fast_path,
+    bench_handoff_no_waiters,
+    bench_handoff_no_waiters_unshared,
+    bench_send_fast_path_lock_free,
     bench_send_with_backpressure,
     bench_multi_threaded_mpsc,
     bench_multi_threaded,
+    bench_ping_pong_park,
+    bench_ping_pong_spin,
 );
 criterion_main!(benches);
@@ -0,0 +1,321 @@
+use super::{
+    channel::{SendError, WaitList, Waiter},
+    ring_buffer::RingBuffer,
+    sync::lock,
+};
+use std::{
+    fmt::Display,
+    future::poll_fn,
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BroadcastRecvError {
+    /// The receiver fell so far behind that `n` messages were overwritten
+    /// before it read them. Its next `recv` continues from the oldest
+    /// message still buffered.
+    Lagged(u64),
+    Closed,
+}
+
+impl Display for BroadcastRecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lagged(n) => write!(f, "Receiver lagged behind by {n} messages"),
+            Self::Closed => write!(f, "Channel closed"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    value: T,
+    unread: usize, // receivers subscribed at send time that haven't read it yet
+}
+
+#[derive(Debug)]
+struct Cursors {
+    oldest: u64, // sequence number of the front of `buffer`
+    receivers: usize,
+}
+
+#[derive(Debug)]
+struct BroadcastInner<T> {
+    buffer: RingBuffer<Slot<T>>,
+    cursors: Mutex<Cursors>, // held across every buffer access
+    waiting_receivers: WaitList,
+    sender_count: Arc<()>,
+}
+
+#[derive(Debug)]
+pub struct BroadcastSender<T> {
+    inner: Arc<BroadcastInner<T>>,
+    _sender_ref: ManuallyDrop<Arc<()>>, // clone of sender_count, released in `drop`
+}
+
+#[derive(Debug)]
+pub struct BroadcastReceiver<T> {
+    inner: Arc<BroadcastInner<T>>,
+    next: u64, // sequence number of the next message to read
+}
+
+/// Creates a channel where every receiver sees every message sent after it
+/// subscribed. A message leaves the buffer once all its receivers have read
+/// it; if the buffer fills first, the oldest message is overwritten and the
+/// receivers that missed it get `BroadcastRecvError::Lagged`.
+pub fn broadcast_channel<T: Clone>(capacity: usize) -> (BroadcastSender<T>, BroadcastReceiver<T>) {
+    let sender_count = Arc::new(());
+
+    let chan = Arc::new(BroadcastInner {
+        buffer: RingBuffer::new(capacity),
+        cursors: Mutex::new(Cursors {
+            oldest: 0,
+            receivers: 1,
+        }),
+        waiting_receivers: WaitList::default(),
+        sender_count: sender_count.clone(),
+    });
+
+    let sender = BroadcastSender {
+        inner: chan.clone(),
+        _sender_ref: ManuallyDrop::new(sender_count),
+    };
+
+    let receiver = BroadcastReceiver {
+        inner: chan,
+        next: 0,
+    };
+
+    (sender, receiver)
+}
+
+impl<T> BroadcastInner<T> {
+    // One reference to the count token is held by `BroadcastInner` itself
+    fn sender_count(&self) -> usize {
+        Arc::strong_count(&self.sender_count) - 1
+    }
+
+    // Slots are read in order, so fully read ones always sit at the front
+    fn pop_fully_read_locked(&self, cursors: &mut Cursors) {
+        while self.buffer.with_slot(0, |slot| slot.unread == 0) == Some(true) {
+            self.buffer.pop();
+            cursors.oldest += 1;
+        }
+    }
+}
+
+impl<T> BroadcastSender<T> {
+    /// Delivers `value` to every current receiver. Never waits: a full
+    /// buffer overwrites its oldest message instead. Fails only if there
+    /// are no receivers.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let inner = &self.inner;
+        let mut cursors = lock(&inner.cursors);
+
+        if cursors.receivers == 0 {
+            return Err(SendError::Closed(value));
+        }
+
+        let slot = Slot {
+            value,
+            unread: cursors.receivers,
+        };
+        if inner.buffer.push_overwrite(slot).is_some() {
+            cursors.oldest += 1;
+        }
+        drop(cursors);
+
+        inner.waiting_receivers.wake_all();
+        Ok(())
+    }
+
+    /// A new receiver that sees messages sent from now on.
+    pub fn subscribe(&self) -> BroadcastReceiver<T> {
+        let mut cursors = lock(&self.inner.cursors);
+        cursors.receivers += 1;
+
+        BroadcastReceiver {
+            inner: self.inner.clone(),
+            next: cursors.oldest + self.inner.buffer.len() as u64,
+        }
+    }
+}
+
+impl<T: Clone> BroadcastReceiver<T> {
+    /// The next message, or `Lagged` if some were overwritten before this
+    /// receiver got to them. `Closed` once every sender is gone and this
+    /// receiver has read everything.
+    pub async fn recv(&mut self) -> Result<T, BroadcastRecvError> {
+        let inner = &*self.inner;
+        let next = &mut self.next;
+
+        let mut waiter = Waiter::new(&inner.waiting_receivers);
+        let res = poll_fn(|cx| {
+            let mut cursors = lock(&inner.cursors);
+
+            if *next < cursors.oldest {
+                let missed = cursors.oldest - *next;
+                *next = cursors.oldest;
+                return Poll::Ready(Err(BroadcastRecvError::Lagged(missed)));
+            }
+
+            let index = (*next - cursors.oldest) as usize;
+            let read = inner.buffer.with_slot(index, |slot| {
+                slot.unread -= 1;
+                slot.value.clone()
+            });
+
+            if let Some(value) = read {
+                *next += 1;
+                inner.pop_fully_read_locked(&mut cursors);
+                return Poll::Ready(Ok(value));
+            }
+
+            if inner.sender_count() == 0 {
+                return Poll::Ready(Err(BroadcastRecvError::Closed));
+            }
+
+            // Registering under `cursors` means a concurrent `send` either
+            // landed before our check or will see this waker
+            waiter.register(cx.waker());
+
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+}
+
+impl<T> Clone for BroadcastSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _sender_ref: self._sender_ref.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BroadcastSender<T> {
+    fn drop(&mut self) {
+        // Release our token before counting, as in `PrioritySender::drop`
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._sender_ref) };
+
+        // Receivers check the count and register under `cursors`, so taking
+        // it here means each one either sees 0 or gets woken
+        let _cursors = lock(&self.inner.cursors);
+        if self.inner.sender_count() == 0 {
+            self.inner.waiting_receivers.wake_all();
+        }
+    }
+}
+
+impl<T> Drop for BroadcastReceiver<T> {
+    fn drop(&mut self) {
+        let inner = &self.inner;
+        let mut cursors = lock(&inner.cursors);
+        cursors.receivers -= 1;
+
+        // Release our claim on everything we never read
+        let start = self.next.saturating_sub(cursors.oldest) as usize;
+        for index in start..inner.buffer.len() {
+            inner.buffer.with_slot(index, |slot| slot.unread -= 1);
+        }
+        inner.pop_fully_read_locked(&mut cursors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{CountingWaker, poll_once};
+
+    #[tokio::test]
+    async fn test_every_receiver_sees_every_message() {
+        let (tx, mut rx1) = broadcast_channel::<u32>(8);
+        let mut rx2 = tx.subscribe();
+
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+
+        for rx in [&mut rx1, &mut rx2] {
+            for i in 0..3 {
+                assert_eq!(rx.recv().await, Ok(i));
+            }
+            assert_eq!(rx.recv().await, Err(BroadcastRecvError::Closed));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_evicted_once_all_have_read() {
+        let (tx, mut rx1) = broadcast_channel::<u32>(4);
+        let mut rx2 = tx.subscribe();
+        tx.send(1).unwrap();
+
+        rx1.recv().await.unwrap();
+        assert_eq!(tx.inner.buffer.len(), 1);
+        rx2.recv().await.unwrap();
+        assert_eq!(tx.inner.buffer.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_slow_receiver_lags() {
+        let (tx, mut rx) = broadcast_channel::<u32>(4);
+
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+
+        assert_eq!(rx.recv().await, Err(BroadcastRecvError::Lagged(2)));
+        assert_eq!(rx.recv().await, Ok(2));
+    }
+
+    #[test]
+    fn test_dropped_recv_leaves_no_waker_behind() {
+        let (tx, mut rx) = broadcast_channel::<u32>(4);
+        let woken = CountingWaker::new();
+
+        let mut recv = Box::pin(rx.recv());
+        for _ in 0..3 {
+            assert!(poll_once(&mut recv, &woken.waker()).is_pending());
+        }
+        assert_eq!(tx.inner.waiting_receivers.len(), 1);
+
+        drop(recv);
+        assert_eq!(tx.inner.waiting_receivers.len(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_last_senders_dropping_together_close_channel() {
+        for _ in 0..50 {
+            let (tx, mut rx) = broadcast_channel::<u32>(4);
+            let recv = tokio::spawn(async move { rx.recv().await });
+            tokio::task::yield_now().await;
+
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+            let droppers: Vec<_> = [tx.clone(), tx]
+                .into_iter()
+                .map(|tx| {
+                    let barrier = barrier.clone();
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        drop(tx);
+                    })
+                })
+                .collect();
+            for dropper in droppers {
+                dropper.join().unwrap();
+            }
+
+            let res = tokio::time::timeout(std::time::Duration::from_secs(5), recv).await;
+            assert_eq!(res.unwrap().unwrap(), Err(BroadcastRecvError::Closed));
+        }
+    }
+}
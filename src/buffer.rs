@@ -0,0 +1,99 @@
+use super::ring_buffer::RingBuffer;
+use alloc::vec::Vec;
+
+/// Storage behind a channel. The channel only handles waiting and waking;
+/// everything about where messages live is up to the implementation.
+///
+/// Every method takes `&self` and may be called from several threads at
+/// once, so implementations synchronize internally. Each call must be
+/// atomic with respect to the others, except that `push_overwrite` may let
+/// a concurrent `push` take the slot it just freed and evict again, as long
+/// as it reports every element it evicted.
+pub trait Buffer<T> {
+    /// Appends `value`, handing it back if there is no room.
+    fn push(&self, value: T) -> Result<(), T>;
+
+    /// Removes the oldest element.
+    fn pop(&self) -> Option<T>;
+
+    /// Pushes `value`, first evicting the oldest elements into `evicted`
+    /// while the buffer is full. Hands `value` back if it still doesn't fit,
+    /// e.g. when there was nothing to evict. Used by
+    /// `OverflowPolicy::DropOldest`.
+    fn push_overwrite<E: FnMut(T)>(&self, value: T, evicted: E) -> Result<(), T>;
+
+    fn len(&self) -> usize;
+
+    /// The most elements the buffer can hold at once.
+    fn max_len(&self) -> usize;
+
+    fn is_full(&self) -> bool;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pops up to `limit` elements into `out`, returning how many were
+    /// moved. Override to take them in one go.
+    fn pop_many(&self, out: &mut Vec<T>, limit: usize) -> usize {
+        let mut count = 0;
+
+        while count < limit
+            && let Some(value) = self.pop()
+        {
+            out.push(value);
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Drops every element. Override to drop them without popping each.
+    fn clear(&self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> Buffer<T> for RingBuffer<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        RingBuffer::push(self, value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        RingBuffer::pop(self)
+    }
+
+    // Evicts at most one element, and only under the lock, so no push can
+    // slip in between
+    fn push_overwrite<E: FnMut(T)>(&self, value: T, mut evicted: E) -> Result<(), T> {
+        if let Some(oldest) = RingBuffer::try_push_overwrite(self, value)? {
+            evicted(oldest);
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        RingBuffer::len(self)
+    }
+
+    // One slot always stays empty
+    fn max_len(&self) -> usize {
+        self.capacity() - 1
+    }
+
+    fn is_full(&self) -> bool {
+        RingBuffer::is_full(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        RingBuffer::is_empty(self)
+    }
+
+    fn pop_many(&self, out: &mut Vec<T>, limit: usize) -> usize {
+        RingBuffer::pop_many(self, out, limit)
+    }
+
+    fn clear(&self) {
+        RingBuffer::clear(self)
+    }
+}
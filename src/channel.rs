@@ -1,458 +1,4762 @@
-use super::ring_buffer::RingBuffer;
+use super::{
+    buffer::Buffer,
+    ring_buffer::{CapacityError, FrontGuard, RingBuffer},
+    sync::lock,
+};
+use futures_core::Stream;
 use std::{
     collections::VecDeque,
     fmt::Display,
+    future::poll_fn,
+    hint,
     marker::PhantomData,
-    sync::{Arc, Mutex},
-    task::{Poll, Waker},
+    mem::ManuallyDrop,
+    ops::Deref,
+    pin::{Pin, pin},
+    ptr::NonNull,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering, fence},
+    },
+    task::{Context, Poll, Wake, Waker, ready},
+    thread::{self, Thread},
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
-#[derive(Debug, Error)]
-pub enum SendError {
-    BufferFull,
-    Closed,
+// Bounds for `Sender::send_adaptive`: a consumer draining a slot every
+// `ADAPTIVE_MIN_TIMEOUT` or faster gets the full `ADAPTIVE_MAX_TIMEOUT`,
+// slower consumers get proportionally less
+const ADAPTIVE_MAX_TIMEOUT: Duration = Duration::from_secs(1);
+const ADAPTIVE_MIN_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A failed send. Both variants hand the unsent value back.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SendError<T> {
+    BufferFull(T),
+    Closed(T),
 }
 
-impl Display for SendError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
         match self {
-            Self::BufferFull => write!(f, "Buffer is full"),
-            Self::Closed => write!(f, "Channel closed"),
+            Self::BufferFull(value) | Self::Closed(value) => value,
         }
     }
 }
 
-pub struct Open;
-pub struct Closing;
-pub struct Closed;
-
-#[derive(Debug)]
-pub struct ChannelInner<T> {
-    buffer: RingBuffer<T>,
-    waiting_senders: Mutex<VecDeque<Waker>>,
-    waiting_receivers: Mutex<VecDeque<Waker>>,
-    sender_count: Arc<()>,
-    capacity: usize,
-}
-
-#[derive(Debug)]
-pub struct Sender<T, S> {
-    inner: Arc<ChannelInner<T>>,
-    _sender_ref: Arc<()>, // clone of sender_count
-    _state: PhantomData<S>,
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BufferFull(_) => write!(f, "Buffer is full"),
+            Self::Closed(_) => write!(f, "Channel closed"),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Receiver<T, S> {
-    inner: Arc<ChannelInner<T>>,
-    _state: PhantomData<S>,
+/// Why `try_send` couldn't send. Both variants hand the value back.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
 }
 
-pub fn channel<T>(capacity: usize) -> (Sender<T, Open>, Receiver<T, Open>) {
-    let sender_count = Arc::new(());
-
-    let chan = Arc::new(ChannelInner {
-        buffer: RingBuffer::new(capacity),
-        waiting_senders: Mutex::new(VecDeque::new()),
-        waiting_receivers: Mutex::new(VecDeque::new()),
-        sender_count: sender_count.clone(),
-        capacity,
-    });
-
-    let sender = Sender {
-        inner: chan.clone(),
-        _sender_ref: sender_count,
-        _state: PhantomData,
-    };
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
 
-    let receiver = Receiver {
-        inner: chan,
-        _state: PhantomData,
-    };
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
 
-    (sender, receiver)
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
 }
 
-impl<T> Sender<T, Open> {
-    pub fn send(&self, value: T) -> SendFuture<'_, T> {
-        SendFuture {
-            sender: self,
-            value: Some(value),
+impl<T> Display for TrySendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "Buffer is full"),
+            Self::Closed(_) => write!(f, "Channel closed"),
         }
     }
 }
 
-impl<T> Receiver<T, Open> {
-    pub fn recv(&self) -> RecvFuture<'_, T> {
-        RecvFuture { receiver: self }
-    }
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
 }
 
-impl<T, S> Clone for Sender<T, S> {
-    fn clone(&self) -> Self {
-        Self {
-            inner: self.inner.clone(),
-            _sender_ref: self._sender_ref.clone(),
-            _state: PhantomData,
+impl Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "Timed out waiting on channel"),
+            Self::Disconnected => write!(f, "Channel closed"),
         }
     }
 }
 
-impl<T, S> Clone for Receiver<T, S> {
-    fn clone(&self) -> Self {
-        Self {
-            inner: self.inner.clone(),
-            _state: PhantomData,
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecvError {
+    Disconnected,
+}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disconnected => write!(f, "Channel closed"),
         }
     }
 }
 
-pub struct SendFuture<'a, T> {
-    sender: &'a Sender<T, Open>,
-    value: Option<T>,
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
 }
 
-impl<'a, T> Future for SendFuture<'a, T>
-where
-    T: Unpin,
-{
-    type Output = Result<(), SendError>;
-
-    fn poll(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
-        let this = self.get_mut();
+impl TryRecvError {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
 
-        let senders_alive = Arc::strong_count(&this.sender.inner.sender_count) >= 1;
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Self::Disconnected)
+    }
+}
 
-        if !senders_alive {
-            return Poll::Ready(Err(SendError::Closed));
+impl Display for TryRecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Channel is empty"),
+            Self::Disconnected => write!(f, "Channel closed"),
         }
+    }
+}
 
-        if let Some(res) = this.value.take() {
-            match this.sender.inner.buffer.push(res) {
-                Ok(()) => {
-                    if let Some(waker) = this
-                        .sender
-                        .inner
-                        .waiting_receivers
-                        .lock()
-                        .unwrap()
-                        .pop_front()
-                    {
-                        waker.wake();
-                    }
-                    Poll::Ready(Ok(()))
-                }
-                Err(rejected) => {
-                    this.sender
-                        .inner
-                        .waiting_senders
-                        .lock()
-                        .unwrap()
-                        .push_back(cx.waker().clone());
-                    this.value = Some(rejected);
-                    Poll::Pending
-                }
-            }
-        } else {
-            Poll::Pending
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),
+    Closed(T),
+}
+
+impl<T> Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout(_) => write!(f, "Timed out waiting for buffer space"),
+            Self::Closed(_) => write!(f, "Channel closed"),
         }
     }
 }
 
-impl<T, S> Drop for Sender<T, S> {
-    fn drop(&mut self) {
-        let count = Arc::strong_count(&self._sender_ref);
+/// Wakes a thread parked in `block_on`. Since it is an ordinary `Waker`,
+/// blocked threads sit in the same waiter queues as async tasks.
+struct ThreadWaker(Thread);
 
-        if count == 2 {
-            let wakers: Vec<_> = self
-                .inner
-                .waiting_receivers
-                .lock()
-                .unwrap()
-                .drain(..)
-                .collect();
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
 
-            for waker in wakers {
-                waker.wake();
-            }
+/// Drives `fut` to completion on the current thread, parking between polls.
+/// `park` may return spuriously, or because someone else unparked the
+/// thread, so every wakeup just re-polls and the future re-checks the buffer.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
         }
+        thread::park();
     }
 }
-pub struct RecvFuture<'a, T> {
-    receiver: &'a Receiver<T, Open>,
-}
 
-impl<'a, T> Future for RecvFuture<'a, T> {
-    type Output = Option<T>;
+/// What a `send` does when the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Park until a receiver makes room.
+    #[default]
+    Block,
+    /// Discard the message being sent.
+    DropNewest,
+    /// Evict the oldest buffered message to make room.
+    DropOldest,
+}
 
-    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let senders_alive = Arc::strong_count(&self.receiver.inner.sender_count) > 1;
-        let buffer_empty = self.receiver.inner.buffer.is_empty();
+pub struct Open;
+pub struct Closing;
+pub struct Closed;
 
-        if !senders_alive && buffer_empty {
-            return Poll::Ready(None);
-        }
-        match self.receiver.inner.buffer.pop() {
-            Some(val) => {
-                if let Some(waker) = self
-                    .receiver
-                    .inner
-                    .waiting_senders
-                    .lock()
-                    .unwrap()
-                    .pop_front()
-                {
-                    waker.wake();
-                }
-                Poll::Ready(Some(val))
-            }
-            None => {
-                self.receiver
-                    .inner
-                    .waiting_receivers
-                    .lock()
-                    .unwrap()
-                    .push_back(cx.waker().clone());
+#[derive(Debug)]
+struct DrainRate {
+    last_pop: Instant,
+    avg_interval: Duration, // moving average of the time between pops
+}
 
-                // Check again after adding to queue!
-                let senders_still_alive = Arc::strong_count(&self.receiver.inner.sender_count) > 1;
-                if !senders_still_alive && self.receiver.inner.buffer.is_empty() {
-                    // Senders died while we were adding to queue
-                    // Remove ourselves and return None
-                    self.receiver
-                        .inner
-                        .waiting_receivers
-                        .lock()
-                        .unwrap()
-                        .pop_back(); // Remove the waker we just added
-                    return Poll::Ready(None);
-                }
+/// Handoff state for capacity-0 channels: a value passes straight from a
+/// sender to a ready receiver and never rests in the ring.
+#[derive(Debug)]
+struct Rendezvous<T> {
+    slot: Option<T>,
+    ready_receivers: usize,
+    deposited: u64, // ticket of the latest value placed in `slot`
+    taken: u64,     // number of values receivers have taken out
+}
 
-                Poll::Pending
-            }
-        }
-    }
+/// Parked tasks in FIFO order. Each waiter is identified by a token rather
+/// than by comparing wakers, since `Waker::will_wake` may report two clones
+/// of the same waker as different.
+#[derive(Debug, Default)]
+struct WaitQueue {
+    waiters: VecDeque<(u64, Waker)>,
+    next_token: u64,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use proptest::prelude::*;
-    use std::collections::HashSet;
-    use test_strategy::proptest as async_proptest;
-    use tokio::task::JoinSet;
+impl WaitQueue {
+    /// Queues `waker` under `token`, assigning one if it has none yet. A
+    /// waiter that is still queued keeps its place; one that was woken but
+    /// lost the race goes to the back, so wakeups rotate fairly.
+    fn register(&mut self, token: &mut Option<u64>, waker: &Waker) {
+        if let Some(id) = *token
+            && let Some((_, queued)) = self.waiters.iter_mut().find(|(t, _)| *t == id)
+        {
+            queued.clone_from(waker);
+            return;
+        }
 
-    #[tokio::test]
-    async fn test_single_send_recv() {
-        let (tx, rx) = channel(8);
-        tx.send(3).await.unwrap();
+        let id = *token.get_or_insert_with(|| {
+            self.next_token += 1;
+            self.next_token
+        });
+        self.waiters.push_back((id, waker.clone()));
+    }
 
-        if let Some(val) = rx.recv().await {
-            assert_eq!(val, 3);
-        }
+    /// Removes the waiter, returning whether it was still queued.
+    fn remove(&mut self, token: u64) -> bool {
+        let before = self.waiters.len();
+        self.waiters.retain(|(t, _)| *t != token);
+        self.waiters.len() != before
     }
+}
 
-    #[tokio::test]
-    async fn test_multi_senders() {
-        let (tx, rx) = channel(4);
-        let tx_clone = tx.clone();
+// Wakers `WaitList::wake` moves out per lock acquisition
+const WAKE_BATCH: usize = 8;
 
-        tx_clone.send(String::from("hello")).await.unwrap();
-        tx.send(String::from("World")).await.unwrap();
+/// A `WaitQueue` behind its lock, with its length mirrored in an atomic so
+/// that waking an empty queue — the common case on an uncontended channel —
+/// skips the lock entirely.
+///
+/// That check races with a task parking, which is resolved the usual way:
+/// a parking task re-checks its condition after `register`, and a waking
+/// task changes the condition before calling `wake`. The `SeqCst` fences in
+/// both guarantee at least one of them sees the other's write.
+#[derive(Debug, Default)]
+pub(crate) struct WaitList {
+    queue: Mutex<WaitQueue>,
+    queued: AtomicUsize,
+}
 
-        if let Some(res) = rx.recv().await {
-            assert!(&res == "hello");
-        }
+impl WaitList {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let queue = WaitQueue {
+            waiters: VecDeque::with_capacity(capacity),
+            next_token: 0,
+        };
 
-        if let Some(res) = rx.recv().await {
-            assert!(&res == "World");
+        Self {
+            queue: Mutex::new(queue),
+            queued: AtomicUsize::new(0),
         }
     }
 
-    #[tokio::test]
-    async fn test_all_senders_drop() {
-        let (tx, rx) = channel(4);
-        tx.send(1).await.unwrap();
-        tx.send(2).await.unwrap();
+    fn register(&self, token: &mut Option<u64>, waker: &Waker) {
+        let mut queue = lock(&self.queue);
+        queue.register(token, waker);
+        self.queued.store(queue.waiters.len(), Ordering::Relaxed);
+        drop(queue);
 
-        drop(tx); // Last sender dropped
+        fence(Ordering::SeqCst);
+    }
 
-        assert_eq!(rx.recv().await, Some(1)); // Should drain
-        assert_eq!(rx.recv().await, Some(2)); // Should drain
-        assert_eq!(rx.recv().await, None); // Should close
+    fn remove(&self, token: u64) -> bool {
+        let mut queue = lock(&self.queue);
+        let removed = queue.remove(token);
+        self.queued.store(queue.waiters.len(), Ordering::Relaxed);
+        removed
     }
 
-    #[tokio::test]
-    async fn test_mpmc_stress() {
-        let (tx, rx) = channel::<u32>(64);
-        let sent = Arc::new(Mutex::new(HashSet::<u32>::new()));
-        let received = Arc::new(Mutex::new(HashSet::<u32>::new()));
+    /// Removes the waiter, or if it was already woken, takes the next one
+    /// to pass the wakeup on to.
+    fn remove_or_take_next(&self, token: u64) -> Option<Waker> {
+        let mut queue = lock(&self.queue);
+        let next = if queue.remove(token) {
+            None
+        } else {
+            queue.waiters.pop_front().map(|(_, waker)| waker)
+        };
+        self.queued.store(queue.waiters.len(), Ordering::Relaxed);
+        next
+    }
 
-        let mut all_handlers = vec![];
+    /// Wakes up to `count` waiters, oldest first. They are dequeued
+    /// `WAKE_BATCH` at a time into a stack array and woken once the lock is
+    /// released, so wake code never runs under it and nothing is allocated.
+    pub(crate) fn wake(&self, mut count: usize) {
+        let mut batch: [Option<Waker>; WAKE_BATCH] = Default::default();
 
-        // 4 senders, each sends 0-2499 with offset
-        // 4 receivers, collect all messages
+        while count > 0 {
+            fence(Ordering::SeqCst);
+            if self.queued.load(Ordering::Relaxed) == 0 {
+                return;
+            }
 
-        for i in 0..4 {
-            let tx = tx.clone();
-            let sent = sent.clone();
-            let handler = tokio::spawn(async move {
-                for j in 1..=2500 {
-                    let res = (j + 2500 * i) as u32;
-                    tx.send(res).await.unwrap();
-                    sent.lock().unwrap().insert(res);
-                }
-            });
-            all_handlers.push(handler);
-        }
+            let mut queue = lock(&self.queue);
+            let n = count.min(WAKE_BATCH).min(queue.waiters.len());
+            for (slot, (_, waker)) in batch.iter_mut().zip(queue.waiters.drain(..n)) {
+                *slot = Some(waker);
+            }
+            self.queued.store(queue.waiters.len(), Ordering::Relaxed);
+            drop(queue);
 
-        drop(tx);
+            // Inside a poll this is attributed to its channel span
+            #[cfg(feature = "tracing")]
+            if n > 0 {
+                tracing::trace!(woken = n, "waking parked tasks");
+            }
 
-        for _ in 1..=4 {
-            let rx = rx.clone();
-            let received = received.clone();
-            let handler = tokio::spawn(async move {
-                while let Some(got) = rx.recv().await {
-                    received.lock().unwrap().insert(got);
-                }
-            });
-            all_handlers.push(handler);
-        }
+            for waker in batch[..n].iter_mut().filter_map(Option::take) {
+                waker.wake();
+            }
+            count -= n;
 
-        for handle in all_handlers {
-            let _ = handle.await;
+            if n < WAKE_BATCH {
+                return; // the queue ran dry
+            }
         }
+    }
 
-        for val in sent.lock().unwrap().iter() {
-            assert_eq!(val, received.lock().unwrap().get(val).unwrap())
-        }
-        assert_eq!(sent.lock().unwrap().len(), 10000);
+    /// Wakes everyone queued as of now. Later arrivals re-check their
+    /// condition after registering, so they need no wakeup.
+    pub(crate) fn wake_all(&self) {
+        fence(Ordering::SeqCst);
+        self.wake(self.queued.load(Ordering::Relaxed));
     }
 
-    #[tokio::test]
-    async fn test_simple_channel_creation() {
-        println!("Before channel creation");
-        let (tx, rx) = channel::<u8>(2);
-        println!("After channel creation");
-        drop(tx);
-        drop(rx);
-        println!("Test complete");
+    pub(crate) fn len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
     }
 
-    #[tokio::test]
-    async fn test_backpressure() {
-        let (tx, rx) = channel::<u8>(4);
-        tx.send(5).await.unwrap();
-        tx.send(3).await.unwrap();
-        tx.send(8).await.unwrap();
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
 
-        println!("Buffer filled");
+/// A pending operation's place in a `WaitList`. Dropping it leaves the
+/// queue, and if it had already been woken, passes the wakeup on to the
+/// next waiter so it isn't lost with the abandoned operation.
+#[derive(Debug)]
+pub(crate) struct Waiter<'a> {
+    list: &'a WaitList,
+    token: Option<u64>,
+}
 
-        let tx_clone = tx.clone();
-        let third_try = tokio::spawn(async move {
-            println!("Third send starting");
-            let result = tx_clone.send(3).await;
-            println!("Third send completed");
-            result
-        });
+impl<'a> Waiter<'a> {
+    pub(crate) fn new(list: &'a WaitList) -> Self {
+        Self { list, token: None }
+    }
 
-        tokio::task::yield_now().await;
-        println!("After yield");
+    /// Picks up a place in the queue kept by `suspend`.
+    fn resume(list: &'a WaitList, token: Option<u64>) -> Self {
+        Self { list, token }
+    }
 
-        assert!(!third_try.is_finished());
-        println!("Assertion passed, dropping tx");
-        drop(tx);
+    /// Hands back our place in the queue for a later `resume`, keeping it.
+    fn suspend(mut self) -> Option<u64> {
+        self.token.take()
+    }
 
-        println!("About to recv");
-        assert_eq!(rx.recv().await, Some(5));
-        println!("Recv completed");
+    pub(crate) fn register(&mut self, waker: &Waker) {
+        self.list.register(&mut self.token, waker);
+    }
 
-        println!("About to await third_try");
-        third_try.await.unwrap().unwrap();
-        println!("Test complete");
+    /// Leaves the queue without passing anything on, for operations that
+    /// completed or gave up on their own.
+    pub(crate) fn deregister(&mut self) {
+        if let Some(token) = self.token.take() {
+            self.list.remove(token);
+        }
     }
+}
 
-    #[ignore]
-    #[async_proptest(async = "tokio")]
-    async fn channel_preserves_all_messages(
-        #[strategy(prop::sample::select(vec![4usize, 8, 16, 32, 64]))] capacity: usize,
-        #[strategy(1usize..=1000)] num_messages: usize,
-    ) {
-        // Send num_messages through channel with capacity
-        // Receive all of them
-        // Assert: all messages arrived, none lost, none duplicated
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let Some(token) = self.token else {
+            return;
+        };
 
-        let sent = Arc::new(Mutex::new(HashSet::<String>::new()));
+        if let Some(next) = self.list.remove_or_take_next(token) {
+            next.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ChannelInner<T, B = RingBuffer<T>> {
+    name: Option<String>, // set by `channel_named`, for telling channels apart
+    buffer: B,
+    waiting_senders: WaitList,
+    waiting_receivers: WaitList,
+    waiting_flushers: WaitList, // `Sender::flush` calls waiting for empty
+    waiting_closed: WaitList,   // `Receiver::closed` calls waiting for no senders
+    senders: AtomicUsize,       // live `Sender`s; never goes back up from 0
+    receiver_count: Arc<()>,
+    drain_rate: Mutex<DrainRate>,
+    rendezvous: Option<Mutex<Rendezvous<T>>>, // set when capacity is 0
+    overflow: OverflowPolicy,
+    overflow_log: Option<RingBuffer<T>>, // keeps what the overflow policy discarded
+    watermarks: Option<Watermarks>,      // set by `channel_with_watermarks`
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    stall_timeout: Option<Duration>,
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    on_stall: fn(&StallReport),
+    spin_before_park: u32,
+    high_water_mark: AtomicUsize, // most messages ever buffered at once
+    closed: AtomicBool,           // set by `Receiver::close`
+    aborted: AtomicBool,          // set by `abort`; receivers take nothing after it
+    total_sent: AtomicU64,
+    total_received: AtomicU64,
+    total_full_rejections: AtomicU64,
+}
+
+pub struct Sender<T, S, B = RingBuffer<T>> {
+    inner: Arc<ChannelInner<T, B>>,
+    poll_state: Mutex<PollSendState>, // the pending `poll_send`, if any
+    _state: PhantomData<S>,
+}
+
+pub struct Receiver<T, S, B = RingBuffer<T>> {
+    inner: Arc<ChannelInner<T, B>>,
+    _receiver_ref: ManuallyDrop<Arc<()>>, // clone of receiver_count, released in `drop`
+    poll_state: Mutex<PollRecvState>,     // the pending `poll_recv`, if any
+    _state: PhantomData<S>,
+}
+
+// What a `SendFuture` or `RecvFuture` keeps between polls, held by the
+// handle itself for `Sender::poll_send` and `Receiver::poll_recv`
+#[derive(Debug, Default)]
+struct PollSendState {
+    token: Option<u64>,
+    ticket: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct PollRecvState {
+    token: Option<u64>,
+    ready: bool,
+}
+
+/// A sender handle that does not keep the channel open. Receivers see the
+/// channel as closed once only `WeakSender`s remain.
+#[derive(Debug)]
+pub struct WeakSender<T, B = RingBuffer<T>> {
+    inner: Weak<ChannelInner<T, B>>,
+}
+
+// What the two `channel_unshared` halves share. Each half holds a raw
+// pointer to it, and whichever drops second frees it
+#[derive(Debug)]
+struct UnsharedInner<T> {
+    buffer: RingBuffer<T>,
+    waiting_senders: WaitList,
+    waiting_receivers: WaitList,
+    sender_alive: AtomicBool,
+    receiver_alive: AtomicBool,
+    halves: AtomicUsize, // halves still holding the pointer
+}
+
+/// The sending half of `channel_unshared`. It can't be cloned, so its
+/// receiver knows it is the only sender.
+#[derive(Debug)]
+pub struct OwnedSender<T> {
+    inner: NonNull<UnsharedInner<T>>,
+    _owns: PhantomData<UnsharedInner<T>>,
+}
+
+/// The receiving half of `channel_unshared`. It can't be cloned.
+#[derive(Debug)]
+pub struct OwnedReceiver<T> {
+    inner: NonNull<UnsharedInner<T>>,
+    _owns: PhantomData<UnsharedInner<T>>,
+}
+
+// SAFETY: The halves only ever hand out `&UnsharedInner`, which is as
+// thread-safe as the `RingBuffer` in it
+unsafe impl<T: Send> Send for OwnedSender<T> {}
+unsafe impl<T: Send> Sync for OwnedSender<T> {}
+unsafe impl<T: Send> Send for OwnedReceiver<T> {}
+unsafe impl<T: Send> Sync for OwnedReceiver<T> {}
+
+/// A receiver that owns the channel's lifetime, made by
+/// `Receiver::into_primary`. Dropping it closes the channel as
+/// `Receiver::close` does, even while other receivers remain: they drain
+/// what is buffered and then see `None`. Derefs to the wrapped `Receiver`.
+#[derive(Debug)]
+pub struct PrimaryReceiver<T, B = RingBuffer<T>> {
+    receiver: Receiver<T, Open, B>,
+}
+
+/// A sender that converts each value before sending it, made by
+/// `Sender::with`. Errors hand back the converted value.
+pub struct MappedSender<U, T, F, B = RingBuffer<T>> {
+    sender: Sender<T, Open, B>,
+    f: F,
+    _input: PhantomData<fn(U)>,
+}
+
+/// Lets code outside the channel nudge parked receivers into re-polling,
+/// e.g. after changing state they check. Woken receivers re-check the
+/// buffer and park again if it is still empty, so a notify never makes
+/// `recv` return early. The handle does not keep the channel alive.
+#[derive(Debug)]
+pub struct NotifyHandle<T, B = RingBuffer<T>> {
+    inner: Weak<ChannelInner<T, B>>,
+}
+
+/// Options for `channel_with_config`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelConfig {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Debug builds only: how long a `send` or `recv` may stay parked before
+    /// `on_stall` is called with the channel's state. `None`, the default,
+    /// disables it.
+    pub stall_timeout: Option<Duration>,
+    pub on_stall: fn(&StallReport),
+    /// How many parked senders, and separately receivers, to make room for
+    /// up front. Queues keep whatever they grow to, so this only saves the
+    /// reallocations on the way up. 0 allocates on the first park.
+    pub expected_waiters: usize,
+    /// How many times a `send` or `recv` retries the buffer, spinning in
+    /// between, before it parks. Saves the park and reschedule when the
+    /// other side is about to make room on another core, at the cost of
+    /// burning CPU when it isn't. Rendezvous channels always park at once.
+    pub spin_before_park: u32,
+}
+
+impl ChannelConfig {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            overflow: OverflowPolicy::Block,
+            stall_timeout: None,
+            on_stall: report_stall,
+            expected_waiters: 0,
+            spin_before_park: 0,
+        }
+    }
+}
+
+/// Lifetime message counts for a channel, as returned by `metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    pub total_sent: u64,
+    pub total_received: u64,
+    /// Pushes that found the buffer full, whether the send then parked,
+    /// failed, or fell back to the overflow policy. A parked send that is
+    /// woken and finds the buffer full again counts once more.
+    pub total_full_rejections: u64,
+}
+
+/// Snapshot of a channel taken when a parked `send` or `recv` stalls, to
+/// help tell a lost wakeup from a consumer that is simply slow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StallReport {
+    pub operation: &'static str,
+    pub len: usize,
+    pub capacity: usize,
+    pub waiting_senders: usize,
+    pub waiting_receivers: usize,
+    pub senders: usize,
+    pub receivers: usize,
+}
+
+impl Display for StallReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Channel {} stalled: {}/{} buffered, {} waiting senders, {} waiting receivers, \
+             {} senders, {} receivers",
+            self.operation,
+            self.len,
+            self.capacity - 1,
+            self.waiting_senders,
+            self.waiting_receivers,
+            self.senders,
+            self.receivers,
+        )
+    }
+}
+
+/// Passed to the `channel_with_watermarks` callback, with the buffer
+/// length right after the crossing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatermarkEvent {
+    /// A send filled the buffer to the high watermark or past it.
+    High { len: usize },
+    /// After a `High`, receives drained the buffer to the low watermark.
+    Low { len: usize },
+}
+
+struct Watermarks {
+    low: usize,
+    high: usize,
+    above: AtomicBool, // between a `High` event and the following `Low`
+    on_cross: Box<dyn Fn(WatermarkEvent) + Send + Sync>,
+}
+
+impl std::fmt::Debug for Watermarks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watermarks")
+            .field("low", &self.low)
+            .field("high", &self.high)
+            .field("above", &self.above)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The default `on_stall` hook, once `stall_timeout` is set: logs the
+/// report as a `tracing` warning with the `tracing` feature, and does
+/// nothing without it. Pass your own `on_stall` to handle it otherwise.
+pub fn report_stall(report: &StallReport) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("{report}");
+    #[cfg(not(feature = "tracing"))]
+    let _ = report;
+}
+
+/// Creates a bounded channel. A `capacity` of 0 makes a rendezvous channel
+/// where each `send` completes only once a receiver has taken the value.
+///
+/// # Panics
+/// If `capacity` is neither 0 nor a power of two of at least 2: 1 is
+/// rejected, since one slot always stays empty. See `try_channel`.
+pub fn channel<T>(capacity: usize) -> (Sender<T, Open>, Receiver<T, Open>) {
+    channel_with_config(ChannelConfig::new(capacity))
+}
+
+/// Like `channel`, but rounds `min_capacity` up as `RingBuffer::new_rounded`
+/// does. Never makes a rendezvous channel, since 0 rounds up too.
+pub fn channel_rounded<T>(min_capacity: usize) -> (Sender<T, Open>, Receiver<T, Open>) {
+    channel(min_capacity.max(2).next_power_of_two())
+}
+
+/// Like `channel`, but reports a `capacity` that is neither 0 nor a power
+/// of two of at least 2 instead of panicking.
+#[allow(clippy::type_complexity)]
+pub fn try_channel<T>(
+    capacity: usize,
+) -> Result<(Sender<T, Open>, Receiver<T, Open>), CapacityError> {
+    let buffer = match capacity {
+        0 => RingBuffer::single_slot(),
+        _ => RingBuffer::try_new(capacity)?,
+    };
+    Ok(build_channel(
+        buffer,
+        ChannelConfig::new(capacity),
+        None,
+        None,
+        None,
+    ))
+}
+
+/// Creates a bounded channel that calls `on_cross` when a send fills the
+/// buffer to `high` messages, and again once receives drain it back down
+/// to `low`. The two events alternate, so a producer can throttle on
+/// `High` and speed back up on `Low` without reacting to every message.
+/// `on_cross` runs on the sending or receiving task, outside the buffer
+/// lock.
+///
+/// # Panics
+/// If `low` isn't below `high`.
+pub fn channel_with_watermarks<T>(
+    capacity: usize,
+    low: usize,
+    high: usize,
+    on_cross: impl Fn(WatermarkEvent) + Send + Sync + 'static,
+) -> (Sender<T, Open>, Receiver<T, Open>) {
+    assert!(low < high, "Low watermark must be below the high one");
+
+    let watermarks = Watermarks {
+        low,
+        high,
+        above: AtomicBool::new(false),
+        on_cross: Box::new(on_cross),
+    };
+    build_channel(
+        ring_for(capacity),
+        ChannelConfig::new(capacity),
+        None,
+        None,
+        Some(watermarks),
+    )
+}
+
+/// Creates a strictly single-producer, single-consumer channel: neither
+/// half can be cloned, so each end can rely on the other being the only
+/// one. The halves share a plain heap allocation instead of an `Arc`, and
+/// skip the overflow policies, metrics and watermarks of `channel`.
+///
+/// # Panics
+/// If `capacity` is less than 2 or not a power of two.
+pub fn channel_unshared<T>(capacity: usize) -> (OwnedSender<T>, OwnedReceiver<T>) {
+    let inner = Box::new(UnsharedInner {
+        buffer: RingBuffer::new(capacity),
+        waiting_senders: WaitList::default(),
+        waiting_receivers: WaitList::default(),
+        sender_alive: AtomicBool::new(true),
+        receiver_alive: AtomicBool::new(true),
+        halves: AtomicUsize::new(2),
+    });
+    let inner = NonNull::from(Box::leak(inner));
+
+    let sender = OwnedSender {
+        inner,
+        _owns: PhantomData,
+    };
+    let receiver = OwnedReceiver {
+        inner,
+        _owns: PhantomData,
+    };
+
+    (sender, receiver)
+}
+
+impl<T> UnsharedInner<T> {
+    /// Gives up one half's pointer, freeing the channel if the other half
+    /// is already gone. `alive` is that half's flag, cleared first so the
+    /// other half's waiters, woken through `peer_waiters`, see it gone.
+    ///
+    /// # Safety
+    /// Each half must call this exactly once, from its `drop`.
+    unsafe fn release(
+        inner: NonNull<Self>,
+        alive: fn(&Self) -> &AtomicBool,
+        peer_waiters: fn(&Self) -> &WaitList,
+    ) {
+        // SAFETY: Our half still counts in `halves`, so nobody frees it yet
+        let this = unsafe { inner.as_ref() };
+        alive(this).store(false, Ordering::Release);
+        peer_waiters(this).wake_all();
+
+        // AcqRel so whichever half frees it has seen everything the other
+        // half did with it
+        if this.halves.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // SAFETY: Made by `Box::leak` in `channel_unshared`, and both
+            // halves are done with it
+            drop(unsafe { Box::from_raw(inner.as_ptr()) });
+        }
+    }
+}
+
+/// Creates a bounded channel tagged with `name`, which both halves report
+/// through `name()` and which shows up in their `Debug` output, to tell
+/// channels apart in logs.
+pub fn channel_named<T>(
+    capacity: usize,
+    name: impl Into<String>,
+) -> (Sender<T, Open>, Receiver<T, Open>) {
+    let config = ChannelConfig::new(capacity);
+    build_channel(ring_for(capacity), config, None, Some(name.into()), None)
+}
+
+/// Creates a bounded channel set up by `config`.
+///
+/// # Panics
+/// If `config.capacity` is neither 0 nor a power of two of at least 2; see
+/// `channel`.
+pub fn channel_with_config<T>(config: ChannelConfig) -> (Sender<T, Open>, Receiver<T, Open>) {
+    build_channel(ring_for(config.capacity), config, None, None, None)
+}
+
+/// Creates a bounded channel that stores its messages in `buffer`, which
+/// decides the capacity. Features that depend on `RingBuffer` internals,
+/// like `reserve` and `resize`, are only available on the default storage.
+pub fn channel_with_buffer<T, B: Buffer<T>>(
+    buffer: B,
+) -> (Sender<T, Open, B>, Receiver<T, Open, B>) {
+    // Only the rendezvous check reads the capacity, and a custom buffer
+    // never makes a rendezvous channel
+    let config = ChannelConfig::new(buffer.max_len() + 1);
+    build_channel(buffer, config, None, None, None)
+}
+
+/// Creates a lossy channel that, instead of silently discarding messages
+/// under `policy`, keeps the most recent `log_capacity - 1` of them for
+/// inspection through `Receiver::overflow_drain`.
+pub fn channel_with_overflow_log<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+    log_capacity: usize,
+) -> (Sender<T, Open>, Receiver<T, Open>) {
+    let config = ChannelConfig {
+        overflow: policy,
+        ..ChannelConfig::new(capacity)
+    };
+
+    build_channel(
+        ring_for(capacity),
+        config,
+        Some(RingBuffer::new(log_capacity)),
+        None,
+        None,
+    )
+}
+
+// A single-slot ring is permanently full, so rendezvous channels can never
+// buffer anything by accident
+fn ring_for<T>(capacity: usize) -> RingBuffer<T> {
+    match capacity {
+        0 => RingBuffer::single_slot(),
+        _ => RingBuffer::new(capacity),
+    }
+}
+
+fn build_channel<T, B>(
+    buffer: B,
+    config: ChannelConfig,
+    overflow_log: Option<RingBuffer<T>>,
+    name: Option<String>,
+    watermarks: Option<Watermarks>,
+) -> (Sender<T, Open, B>, Receiver<T, Open, B>) {
+    let ChannelConfig {
+        capacity,
+        overflow,
+        stall_timeout,
+        on_stall,
+        expected_waiters,
+        spin_before_park,
+    } = config;
+    let receiver_count = Arc::new(());
+
+    let chan = Arc::new(ChannelInner {
+        name,
+        buffer,
+        waiting_senders: WaitList::with_capacity(expected_waiters),
+        waiting_receivers: WaitList::with_capacity(expected_waiters),
+        waiting_flushers: WaitList::default(),
+        waiting_closed: WaitList::default(),
+        senders: AtomicUsize::new(1),
+        receiver_count: receiver_count.clone(),
+        drain_rate: Mutex::new(DrainRate {
+            last_pop: Instant::now(),
+            avg_interval: Duration::ZERO,
+        }),
+        rendezvous: (capacity == 0).then(|| {
+            Mutex::new(Rendezvous {
+                slot: None,
+                ready_receivers: 0,
+                deposited: 0,
+                taken: 0,
+            })
+        }),
+        overflow,
+        overflow_log,
+        watermarks,
+        stall_timeout,
+        on_stall,
+        spin_before_park,
+        high_water_mark: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        aborted: AtomicBool::new(false),
+        total_sent: AtomicU64::new(0),
+        total_received: AtomicU64::new(0),
+        total_full_rejections: AtomicU64::new(0),
+    });
+
+    let sender = Sender {
+        inner: chan.clone(),
+        poll_state: Mutex::default(),
+        _state: PhantomData,
+    };
+
+    let receiver = Receiver {
+        inner: chan,
+        _receiver_ref: ManuallyDrop::new(receiver_count),
+        poll_state: Mutex::default(),
+        _state: PhantomData,
+    };
+
+    (sender, receiver)
+}
+
+impl<T, B> ChannelInner<T, B> {
+    fn sender_count(&self) -> usize {
+        self.senders.load(Ordering::Acquire)
+    }
+
+    // One reference to the count token is held by `ChannelInner` itself
+    fn receiver_count(&self) -> usize {
+        Arc::strong_count(&self.receiver_count) - 1
+    }
+
+    /// Whether sends must fail: nobody will receive them any more.
+    fn send_closed(&self) -> bool {
+        self.receiver_count() == 0 || self.closed.load(Ordering::Acquire)
+    }
+
+    /// Whether nothing new can arrive, so receivers stop once the buffer
+    /// is drained.
+    fn recv_closed(&self) -> bool {
+        self.sender_count() == 0 || self.closed.load(Ordering::Acquire)
+    }
+
+    /// Whether `abort` ran, so receivers must return `None` even if a send
+    /// that raced it left a message behind.
+    fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+
+        self.wake_all_senders();
+        self.wake_all_receivers();
+    }
+
+    /// Wakes up to `count` parked receivers, one per message just enqueued.
+    fn wake_n(&self, count: usize) {
+        self.waiting_receivers.wake(count);
+    }
+
+    fn wake_n_senders(&self, count: usize) {
+        self.waiting_senders.wake(count);
+    }
+
+    fn wake_all_flushers(&self) {
+        self.waiting_flushers.wake_all();
+    }
+
+    /// Wakes everything waiting on senders once the last one is gone.
+    fn on_senders_gone(&self) {
+        self.wake_all_receivers();
+        self.waiting_closed.wake_all();
+    }
+
+    /// Bookkeeping after a receiver took a sender's value from the
+    /// rendezvous slot.
+    fn on_handoff(&self) {
+        self.total_sent.fetch_add(1, Ordering::Relaxed);
+        self.total_received.fetch_add(1, Ordering::Relaxed);
+
+        // The depositing sender is parked among the others
+        self.wake_all_senders();
+        self.wake_all_flushers();
+    }
+
+    /// Whether a rendezvous value was handed off but no receiver has taken
+    /// it yet.
+    fn handoff_pending(&self) -> bool {
+        self.rendezvous
+            .as_ref()
+            .is_some_and(|rendezvous| lock(rendezvous).slot.is_some())
+    }
+
+    fn record_full_rejection(&self) {
+        self.total_full_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn metrics(&self) -> ChannelMetrics {
+        ChannelMetrics {
+            total_sent: self.total_sent.load(Ordering::Relaxed),
+            total_received: self.total_received.load(Ordering::Relaxed),
+            total_full_rejections: self.total_full_rejections.load(Ordering::Relaxed),
+        }
+    }
+
+    fn high_water_mark(&self) -> usize {
+        self.high_water_mark.load(Ordering::Relaxed)
+    }
+
+    fn wake_all_receivers(&self) {
+        self.waiting_receivers.wake_all();
+    }
+
+    fn wake_all_senders(&self) {
+        self.waiting_senders.wake_all();
+    }
+
+    fn record_drain(&self) {
+        let mut rate = lock(&self.drain_rate);
+        let now = Instant::now();
+        let sample = now - rate.last_pop;
+
+        rate.avg_interval = (rate.avg_interval * 7 + sample) / 8;
+        rate.last_pop = now;
+    }
+
+    fn adaptive_timeout(&self) -> Duration {
+        let rate = lock(&self.drain_rate);
+
+        // A consumer that hasn't popped for a while is at least that slow
+        let interval = rate.avg_interval.max(rate.last_pop.elapsed());
+        let scale = ADAPTIVE_MIN_TIMEOUT.as_secs_f64() / interval.as_secs_f64();
+
+        ADAPTIVE_MAX_TIMEOUT
+            .mul_f64(scale.min(1.0))
+            .max(ADAPTIVE_MIN_TIMEOUT)
+    }
+}
+
+impl<T, B: Buffer<T>> ChannelInner<T, B> {
+    /// Bookkeeping after senders put `count` messages into the buffer.
+    fn on_push(&self, count: usize) {
+        // A send that passed its closed check before `abort` may have pushed
+        // after the abort's clear. Nobody may receive it, so drop it here.
+        if self.aborted() {
+            self.buffer.clear();
+            return;
+        }
+
+        self.total_sent.fetch_add(count as u64, Ordering::Relaxed);
+        self.high_water_mark
+            .fetch_max(self.buffer.len(), Ordering::Relaxed);
+        self.wake_n(count);
+
+        if let Some(marks) = &self.watermarks {
+            let len = self.buffer.len();
+            if len >= marks.high && !marks.above.swap(true, Ordering::AcqRel) {
+                (marks.on_cross)(WatermarkEvent::High { len });
+            }
+        }
+    }
+
+    // Restarts from the current length rather than 0, since those messages
+    // are still buffered in the new interval
+    fn reset_high_water_mark(&self) -> usize {
+        self.high_water_mark
+            .swap(self.buffer.len(), Ordering::Relaxed)
+    }
+
+    /// Bookkeeping after receivers took `count` messages out of the buffer.
+    fn on_pop(&self, count: usize) {
+        self.total_received
+            .fetch_add(count as u64, Ordering::Relaxed);
+        self.record_drain();
+        self.wake_n_senders(count);
+
+        if self.buffer.is_empty() {
+            self.wake_all_flushers();
+        } else {
+            // Whatever is left may have been meant for a receiver whose
+            // wakeup this one used up, so pass it on
+            self.wake_n(1);
+        }
+
+        if let Some(marks) = &self.watermarks {
+            let len = self.buffer.len();
+            if len <= marks.low && marks.above.swap(false, Ordering::AcqRel) {
+                (marks.on_cross)(WatermarkEvent::Low { len });
+            }
+        }
+    }
+
+    /// Closes the channel and drops whatever is buffered. Receivers woken by
+    /// the close find the buffer empty and return `None`.
+    fn abort(&self) {
+        // Flagged before clearing, so any push the clear misses finds the
+        // flag in `on_push`, and no receiver takes it in the meantime
+        self.aborted.store(true, Ordering::SeqCst);
+        self.buffer.clear();
+        self.close();
+
+        // A value mid hand-off is discarded as a buffered one would be.
+        // Counting it taken lets its sender's wait complete.
+        if let Some(rendezvous) = &self.rendezvous {
+            let mut state = lock(rendezvous);
+            let discarded = state.slot.take();
+            if discarded.is_some() {
+                state.taken += 1;
+            }
+            drop(state);
+
+            drop(discarded);
+            self.wake_all_senders();
+        }
+        self.wake_all_flushers();
+
+        if let Some(marks) = &self.watermarks
+            && marks.above.swap(false, Ordering::AcqRel)
+        {
+            (marks.on_cross)(WatermarkEvent::Low { len: 0 });
+        }
+    }
+
+    /// Retries a push the full buffer rejected, up to `spin_before_park`
+    /// times, handing `value` back if there is still no room.
+    fn spin_push(&self, mut value: T) -> Result<(), T> {
+        for _ in 0..self.spin_before_park {
+            hint::spin_loop();
+            match self.buffer.push(value) {
+                Ok(()) => return Ok(()),
+                Err(rejected) => value = rejected,
+            }
+        }
+
+        Err(value)
+    }
+
+    /// Retries a pop that found the buffer empty, up to `spin_before_park`
+    /// times.
+    fn spin_pop(&self) -> Option<T> {
+        (0..self.spin_before_park).find_map(|_| {
+            hint::spin_loop();
+            self.buffer.pop()
+        })
+    }
+
+    /// Applies a lossy overflow policy to a value the full buffer rejected.
+    fn handle_overflow(&self, rejected: T) {
+        self.record_full_rejection();
+
+        // A full log forgets its oldest entry, keeping memory bounded
+        let discard = |value| {
+            if let Some(log) = &self.overflow_log {
+                log.push_overwrite(value);
+            }
+        };
+
+        match self.overflow {
+            OverflowPolicy::Block => unreachable!("blocking sends park instead"),
+            OverflowPolicy::DropNewest => discard(rejected),
+            OverflowPolicy::DropOldest => match self.buffer.push_overwrite(rejected, discard) {
+                Ok(()) => self.on_push(1),
+                // Nothing to evict, as every free slot is reserved, so the
+                // new message is the one discarded
+                Err(rejected) => discard(rejected),
+            },
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn poll_span(&self, operation: &'static str) -> tracing::Span {
+        tracing::trace_span!("channel", operation, name = self.name.as_deref())
+    }
+
+    /// Records how a poll inside `poll_span` ended.
+    #[cfg(feature = "tracing")]
+    fn trace_poll(&self, completed: bool) {
+        let len = self.buffer.len();
+        let capacity = self.buffer.max_len();
+
+        if completed {
+            tracing::trace!(len, capacity, "completed");
+        } else {
+            tracing::trace!(len, capacity, "parked");
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn stall_report(&self, operation: &'static str) -> StallReport {
+        StallReport {
+            operation,
+            len: self.buffer.len(),
+            // Reported in slots, one of which a ring always leaves empty
+            capacity: self.buffer.max_len() + 1,
+            waiting_senders: self.waiting_senders.len(),
+            waiting_receivers: self.waiting_receivers.len(),
+            senders: self.sender_count(),
+            receivers: self.receiver_count(),
+        }
+    }
+}
+
+impl<T> ChannelInner<T> {
+    fn resize(&self, new_capacity: usize) {
+        assert!(
+            self.rendezvous.is_none(),
+            "Rendezvous channels have no buffer to resize"
+        );
+
+        let old_capacity = self.buffer.capacity();
+        self.buffer.resize(new_capacity);
+
+        // Parked senders re-check fullness against the new capacity when
+        // polled, so any added room is enough reason to wake them all. One
+        // parking concurrently sees the new capacity in its own re-check.
+        if new_capacity > old_capacity {
+            self.wake_all_senders();
+        }
+    }
+}
+
+impl<T, S, B: Buffer<T>> Sender<T, S, B> {
+    /// Number of live senders, this one included.
+    pub fn sender_count(&self) -> usize {
+        self.inner.sender_count()
+    }
+
+    /// Number of live receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+
+    /// Whether sends will fail because no receiver is left or one called
+    /// `close`. Worth checking before producing an expensive message.
+    pub fn is_closed(&self) -> bool {
+        self.inner.send_closed()
+    }
+
+    /// How many sends are parked waiting for room. Parked senders alongside
+    /// a buffer with room left point at a lost wakeup.
+    pub fn waiting_senders_len(&self) -> usize {
+        self.inner.waiting_senders.len()
+    }
+
+    /// Whether both handles send into the same channel.
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// The name given to `channel_named`, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// The most messages the buffer has held at once since the channel was
+    /// created or the mark was last reset.
+    pub fn high_water_mark(&self) -> usize {
+        self.inner.high_water_mark()
+    }
+
+    /// Starts a new sampling interval, returning the mark reached in the
+    /// previous one. The new mark starts at the current length.
+    pub fn reset_high_water_mark(&self) -> usize {
+        self.inner.reset_high_water_mark()
+    }
+
+    /// Lifetime sent/received/rejected counts. Cheap enough to poll from a
+    /// metrics exporter.
+    pub fn metrics(&self) -> ChannelMetrics {
+        self.inner.metrics()
+    }
+}
+
+impl<T, S, B: Buffer<T>> Receiver<T, S, B> {
+    /// Number of live senders.
+    pub fn sender_count(&self) -> usize {
+        self.inner.sender_count()
+    }
+
+    /// Number of live receivers, this one included.
+    pub fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+
+    /// Whether `recv` would return `None`: nothing new can arrive and the
+    /// buffer is drained.
+    pub fn is_closed(&self) -> bool {
+        self.inner.aborted() || (self.inner.recv_closed() && self.inner.buffer.is_empty())
+    }
+
+    /// How many receives are parked waiting for a message. Parked receivers
+    /// alongside a non-empty buffer point at a lost wakeup.
+    pub fn waiting_receivers_len(&self) -> usize {
+        self.inner.waiting_receivers.len()
+    }
+
+    /// Whether both handles receive from the same channel.
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// The name given to `channel_named`, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// The most messages the buffer has held at once since the channel was
+    /// created or the mark was last reset.
+    pub fn high_water_mark(&self) -> usize {
+        self.inner.high_water_mark()
+    }
+
+    /// Starts a new sampling interval, returning the mark reached in the
+    /// previous one. The new mark starts at the current length.
+    pub fn reset_high_water_mark(&self) -> usize {
+        self.inner.reset_high_water_mark()
+    }
+
+    /// Lifetime sent/received/rejected counts. Cheap enough to poll from a
+    /// metrics exporter.
+    pub fn metrics(&self) -> ChannelMetrics {
+        self.inner.metrics()
+    }
+}
+
+impl<T, B: Buffer<T>> Sender<T, Open, B> {
+    /// Wraps this sender so callers send `U`s, each turned into a `T` by
+    /// `f` on the way in. The wrapper still counts as a live sender.
+    pub fn with<U, F: Fn(U) -> T>(self, f: F) -> MappedSender<U, T, F, B> {
+        MappedSender {
+            sender: self,
+            f,
+            _input: PhantomData,
+        }
+    }
+
+    /// Tears the channel down: closes it like `Receiver::close`, but also
+    /// drops every buffered message, so parked receivers resolve to `None`
+    /// instead of draining. A value mid hand-off on a rendezvous channel is
+    /// dropped too, and its `send` completes as if a receiver had taken it.
+    pub fn abort(&self) {
+        self.inner.abort();
+    }
+
+    pub fn downgrade(&self) -> WeakSender<T, B> {
+        WeakSender {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    pub fn send(&self, value: T) -> SendFuture<'_, T, B> {
+        SendFuture {
+            sender: self,
+            value: Some(value),
+            ticket: None,
+            waiter: Waiter::new(&self.inner.waiting_senders),
+            #[cfg(debug_assertions)]
+            watchdog: StallWatchdog::Idle,
+        }
+    }
+
+    /// `send` for hand-written futures: takes the message out of `value`
+    /// once it is sent or the send fails, and leaves it there while the
+    /// channel is full, registering `cx`'s waker. Keep passing the same
+    /// `value` until this returns `Ready`. As with `Receiver::poll_recv`,
+    /// only one task at a time should poll a given handle.
+    pub fn poll_send(
+        &self,
+        value: &mut Option<T>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        let mut state = lock(&self.poll_state);
+        let PollSendState { token, ticket } = &mut *state;
+
+        let mut waiter = Waiter::resume(&self.inner.waiting_senders, token.take());
+        let res = self.poll_send_with(value, ticket, &mut waiter, cx);
+        if res.is_ready() {
+            waiter.deregister();
+        }
+        *token = waiter.suspend();
+
+        res
+    }
+
+    /// Blocking counterpart of `send` for threads outside an async runtime.
+    /// Must not be called from within an async task.
+    pub fn send_blocking(&self, value: T) -> Result<(), SendError<T>> {
+        block_on(self.send(value))
+    }
+
+    /// Like `send`, but hands the value back in `SendTimeoutError::Timeout`
+    /// if the buffer stays full for `dur`.
+    pub fn send_timeout(&self, value: T, dur: Duration) -> SendTimeoutFuture<'_, T, B> {
+        SendTimeoutFuture {
+            send: self.send(value),
+            sleep: Box::pin(tokio::time::sleep(dur)),
+        }
+    }
+
+    /// Sends without waiting, failing with `Full` if there is no room.
+    /// Lossy overflow policies apply as they would for `send`.
+    ///
+    /// On a rendezvous channel this succeeds only if a receiver is already
+    /// parked in `recv` with the slot free; the value is handed to it
+    /// directly. Otherwise it fails with `Full`.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.inner.send_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+
+        if let Some(rendezvous) = &self.inner.rendezvous {
+            return self.try_handoff(rendezvous, value);
+        }
+
+        match self.inner.buffer.push(value) {
+            Ok(()) => {
+                self.inner.on_push(1);
+                Ok(())
+            }
+            Err(rejected) if self.inner.overflow != OverflowPolicy::Block => {
+                self.inner.handle_overflow(rejected);
+                Ok(())
+            }
+            Err(rejected) => {
+                self.inner.record_full_rejection();
+                Err(TrySendError::Full(rejected))
+            }
+        }
+    }
+
+    fn try_handoff(
+        &self,
+        rendezvous: &Mutex<Rendezvous<T>>,
+        value: T,
+    ) -> Result<(), TrySendError<T>> {
+        let mut state = lock(rendezvous);
+
+        // `try_send` checked before locking, but an `abort` may have run since
+        if self.inner.send_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+        if state.slot.is_some() || state.ready_receivers == 0 {
+            drop(state);
+            self.inner.record_full_rejection();
+            return Err(TrySendError::Full(value));
+        }
+
+        // Nobody waits on this ticket; the receiver counts the handoff
+        state.slot = Some(value);
+        state.deposited += 1;
+        drop(state);
+
+        self.inner.wake_n(1);
+        Ok(())
+    }
+
+    /// Waits until receivers have taken every buffered message, including a
+    /// value `try_send` left for a rendezvous receiver, or until there are
+    /// no receivers left to take them.
+    pub async fn flush(&self) {
+        let inner = &self.inner;
+        let drained =
+            || (inner.buffer.is_empty() && !inner.handoff_pending()) || inner.receiver_count() == 0;
+
+        let mut waiter = Waiter::new(&inner.waiting_flushers);
+        poll_fn(|cx| {
+            if drained() {
+                return Poll::Ready(());
+            }
+
+            waiter.register(cx.waker());
+
+            if drained() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+    }
+
+    /// Sends the items in order, waiting for room before each one as `send`
+    /// does, unlike `send_all_or_cancel` which waits for room for the lot.
+    /// Stops at the first item the channel is closed to and hands it back;
+    /// later items stay in the iterator, so pass `&mut iter` to keep them.
+    ///
+    /// Dropping the future part-way leaves every item either sent or still
+    /// owned by the caller's iterator, apart from the one being sent, which
+    /// is dropped with the future if it hadn't made it into the buffer.
+    pub async fn send_all<I: IntoIterator<Item = T>>(&self, items: I) -> Result<(), SendError<T>> {
+        for item in items {
+            self.send(item).await?;
+        }
+        Ok(())
+    }
+
+    /// Like `send`, but gives up with `SendError::BufferFull` if the buffer
+    /// stays full past `adaptive_timeout()`.
+    pub async fn send_adaptive(&self, value: T) -> Result<(), SendError<T>> {
+        let timeout = self.adaptive_timeout();
+
+        self.send_timeout(value, timeout)
+            .await
+            .map_err(|err| match err {
+                SendTimeoutError::Timeout(value) => SendError::BufferFull(value),
+                SendTimeoutError::Closed(value) => SendError::Closed(value),
+            })
+    }
+
+    /// The timeout `send_adaptive` would currently use, derived from how
+    /// quickly receivers have been draining the buffer.
+    pub fn adaptive_timeout(&self) -> Duration {
+        self.inner.adaptive_timeout()
+    }
+}
+
+impl<T> Sender<T, Open> {
+    /// Sends the items as one batch: waits until there is room for all of
+    /// them, then enqueues them under a single lock so receivers never see a
+    /// partial batch. Fails without enqueuing anything if the batch can never
+    /// fit in the buffer or every receiver is gone.
+    pub async fn send_all_or_cancel<I: IntoIterator<Item = T>>(
+        &self,
+        iter: I,
+    ) -> Result<(), SendError<Vec<T>>> {
+        let items: Vec<T> = iter.into_iter().collect();
+        let len = items.len();
+
+        if len >= self.inner.buffer.capacity() {
+            return Err(SendError::BufferFull(items));
+        }
+
+        let mut batch = Some(items);
+        let mut waiter = Waiter::new(&self.inner.waiting_senders);
+        let res = poll_fn(|cx| {
+            let items = batch.take().expect("polled after completion");
+
+            if self.inner.send_closed() {
+                return Poll::Ready(Err(SendError::Closed(items)));
+            }
+
+            match self.inner.buffer.push_all(items) {
+                Ok(()) => {
+                    self.inner.on_push(len);
+                    Poll::Ready(Ok(()))
+                }
+                Err(rejected) => {
+                    self.inner.record_full_rejection();
+                    batch = Some(rejected);
+                    waiter.register(cx.waker());
+
+                    if self.inner.buffer.remaining() >= len || self.inner.send_closed() {
+                        cx.waker().wake_by_ref();
+                    }
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+
+    /// Waits for a free slot and sets it aside, so the message can be built
+    /// only once it is known to fit. Other sends treat the slot as taken
+    /// until the returned `Permit` is used or dropped.
+    ///
+    /// # Panics
+    /// On rendezvous channels, which have no slots to reserve.
+    pub async fn reserve(&self) -> Result<Permit<'_, T>, SendError<()>> {
+        assert!(
+            self.inner.rendezvous.is_none(),
+            "Rendezvous channels have no slots to reserve"
+        );
+
+        let mut waiter = Waiter::new(&self.inner.waiting_senders);
+        let res = poll_fn(|cx| {
+            if self.inner.send_closed() {
+                return Poll::Ready(Err(SendError::Closed(())));
+            }
+
+            if self.inner.buffer.try_reserve() {
+                return Poll::Ready(Ok(Permit {
+                    sender: self,
+                    used: false,
+                }));
+            }
+
+            waiter.register(cx.waker());
+
+            if !self.inner.buffer.is_full() || self.inner.send_closed() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+
+    /// Like `reserve`, but fails with `BufferFull` instead of waiting, for
+    /// callers that only build the message if it can go out right away.
+    ///
+    /// # Panics
+    /// On rendezvous channels, which have no slots to reserve.
+    pub fn try_reserve(&self) -> Result<Permit<'_, T>, SendError<()>> {
+        assert!(
+            self.inner.rendezvous.is_none(),
+            "Rendezvous channels have no slots to reserve"
+        );
+
+        if self.inner.send_closed() {
+            return Err(SendError::Closed(()));
+        }
+
+        if !self.inner.buffer.try_reserve() {
+            return Err(SendError::BufferFull(()));
+        }
+
+        Ok(Permit {
+            sender: self,
+            used: false,
+        })
+    }
+
+    /// Waits until `n` slots are free and sets them all aside for one
+    /// burst. Messages sent through the returned `PermitMany` are enqueued
+    /// together when it drops, so no other sender's message lands between
+    /// them. Fails with `BufferFull` if `n` slots can never be free at once.
+    ///
+    /// # Panics
+    /// On rendezvous channels, which have no slots to reserve.
+    pub async fn reserve_many(&self, n: usize) -> Result<PermitMany<'_, T>, SendError<()>> {
+        assert!(
+            self.inner.rendezvous.is_none(),
+            "Rendezvous channels have no slots to reserve"
+        );
+
+        if n >= self.inner.buffer.capacity() {
+            return Err(SendError::BufferFull(()));
+        }
+
+        let mut waiter = Waiter::new(&self.inner.waiting_senders);
+        let res = poll_fn(|cx| {
+            if self.inner.send_closed() {
+                return Poll::Ready(Err(SendError::Closed(())));
+            }
+
+            if self.inner.buffer.try_reserve_many(n) {
+                return Poll::Ready(Ok(PermitMany {
+                    sender: self,
+                    unused: n,
+                    pending: Vec::with_capacity(n),
+                }));
+            }
+
+            waiter.register(cx.waker());
+
+            if self.inner.buffer.remaining() >= n || self.inner.send_closed() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+
+    /// Reallocates the channel's buffer to `new_capacity` (a power of two
+    /// large enough for the queued messages), waking parked senders if room
+    /// was added.
+    ///
+    /// # Panics
+    /// If `new_capacity` is less than 2 or not a power of two, or too small
+    /// for the queued messages; see `RingBuffer::resize`.
+    pub fn resize(&self, new_capacity: usize) {
+        self.inner.resize(new_capacity);
+    }
+}
+
+impl<T, B: Buffer<T>> Receiver<T, Open, B> {
+    /// Receives the next message, waiting while the buffer is empty.
+    ///
+    /// The buffer is a single locked FIFO, so messages leave it in the
+    /// order they entered and each sender's messages arrive in send order.
+    /// With several receivers that sequence is split between them: every
+    /// receiver sees any one sender's messages in order, but nothing orders
+    /// what different receivers observe relative to each other.
+    ///
+    /// Cancel safe: a message only leaves the channel in the poll that
+    /// returns it, so dropping the future (e.g. a losing `select!` branch)
+    /// never loses one. A wakeup it received is passed on to another
+    /// parked receiver, and a rendezvous value a sender deposited for it
+    /// stays in the slot for the next `recv`.
+    pub fn recv(&self) -> RecvFuture<'_, T, B> {
+        RecvFuture {
+            receiver: self,
+            ready: false,
+            waiter: Waiter::new(&self.inner.waiting_receivers),
+            #[cfg(debug_assertions)]
+            watchdog: StallWatchdog::Idle,
+        }
+    }
+
+    /// `recv` for hand-written futures: polls for the next message without
+    /// a `RecvFuture`, registering `cx`'s waker when it returns `Pending`.
+    /// The handle keeps that registration between calls, so only one task
+    /// at a time should poll a given handle; give others a clone.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = lock(&self.poll_state);
+        let PollRecvState { token, ready } = &mut *state;
+
+        let mut waiter = Waiter::resume(&self.inner.waiting_receivers, token.take());
+        let res = self.poll_recv_with(&mut waiter, ready, cx);
+        if res.is_ready() {
+            waiter.deregister();
+        }
+        *token = waiter.suspend();
+
+        res
+    }
+
+    /// Like `recv`, but reports a closed channel as
+    /// `RecvError::Disconnected` so consumers can use `?`.
+    pub fn recv_result(&self) -> RecvResultFuture<'_, T, B> {
+        RecvResultFuture { recv: self.recv() }
+    }
+
+    /// The messages as a `Stream`, ending once the channel is closed and
+    /// drained. Its `size_hint` counts what is buffered right now as the
+    /// lower bound; more may always arrive, so there is no upper bound
+    /// until the stream has ended.
+    pub fn stream(&self) -> RecvStream<'_, T, B> {
+        RecvStream {
+            recv: self.recv(),
+            done: false,
+        }
+    }
+
+    /// Stops the channel from accepting messages, failing every pending and
+    /// future send with `SendError::Closed`. Messages already buffered can
+    /// still be received; after them `recv` returns `None`.
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Closes the channel and drops every buffered message. See
+    /// `Sender::abort`.
+    pub fn abort(&self) {
+        self.inner.abort();
+    }
+
+    /// Makes this the channel's primary receiver, whose drop closes the
+    /// channel for everyone regardless of other receiver clones.
+    pub fn into_primary(self) -> PrimaryReceiver<T, B> {
+        PrimaryReceiver { receiver: self }
+    }
+
+    /// Resolves once every sender is gone, whether or not messages are
+    /// still buffered.
+    pub async fn closed(&self) {
+        let inner = &self.inner;
+
+        let mut waiter = Waiter::new(&inner.waiting_closed);
+        poll_fn(|cx| {
+            if inner.sender_count() == 0 {
+                return Poll::Ready(());
+            }
+
+            waiter.register(cx.waker());
+
+            if inner.sender_count() == 0 {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+    }
+
+    pub fn notify_handle(&self) -> NotifyHandle<T, B> {
+        NotifyHandle {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Waits for at least one message, then moves up to `limit` of them into
+    /// `out` under a single lock acquisition. Returns how many were received,
+    /// which is 0 only once the channel is closed and empty (or `limit` is 0).
+    pub async fn recv_many(&self, out: &mut Vec<T>, limit: usize) -> usize {
+        if limit == 0 {
+            return 0;
+        }
+
+        // Rendezvous channels never buffer, so values arrive one at a time
+        if self.inner.rendezvous.is_some() {
+            return match self.recv().await {
+                Some(value) => {
+                    out.push(value);
+                    1
+                }
+                None => 0,
+            };
+        }
+
+        let mut waiter = Waiter::new(&self.inner.waiting_receivers);
+        let count = poll_fn(|cx| {
+            let inner = &self.inner;
+            if inner.aborted() {
+                return Poll::Ready(0);
+            }
+
+            let count = inner.buffer.pop_many(out, limit);
+
+            if count > 0 {
+                inner.on_pop(count);
+                return Poll::Ready(count);
+            }
+
+            if inner.recv_closed() && inner.buffer.is_empty() {
+                return Poll::Ready(0);
+            }
+
+            waiter.register(cx.waker());
+
+            if inner.recv_closed() || !inner.buffer.is_empty() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        count
+    }
+
+    /// Like `recv_many`, but gives up after `dur` if nothing has arrived.
+    /// Once the first message is in, it takes whatever else is buffered
+    /// without waiting further. Returns 0 on timeout as well as on a closed,
+    /// empty channel; the timer and the parked waker go away either way.
+    pub async fn recv_many_timeout(&self, out: &mut Vec<T>, limit: usize, dur: Duration) -> usize {
+        tokio::time::timeout(dur, self.recv_many(out, limit))
+            .await
+            .unwrap_or(0)
+    }
+
+    /// Blocking counterpart of `recv` for threads outside an async runtime.
+    /// Must not be called from within an async task.
+    pub fn recv_blocking(&self) -> Option<T> {
+        block_on(self.recv())
+    }
+
+    /// Takes a message if one is ready, without waiting. On a rendezvous
+    /// channel that only succeeds while a sender's value sits in the handoff.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let inner = &self.inner;
+        if inner.aborted() {
+            return Err(TryRecvError::Disconnected);
+        }
+
+        let value = match &inner.rendezvous {
+            Some(rendezvous) => {
+                let mut state = lock(rendezvous);
+                let value = state.slot.take();
+                if value.is_some() {
+                    state.taken += 1;
+                }
+                value
+            }
+            None => inner.buffer.pop(),
+        };
+
+        match value {
+            Some(value) => {
+                if inner.rendezvous.is_some() {
+                    inner.on_handoff();
+                } else {
+                    inner.on_pop(1);
+                }
+                Ok(value)
+            }
+            None if inner.recv_closed() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Yields the messages that are ready right now, stopping once the
+    /// channel is empty.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Yields each message as it arrives, blocking in between, until the
+    /// channel closes. Must not be used from within an async task.
+    pub fn iter_blocking(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv_blocking())
+    }
+
+    /// Takes every message the overflow policy has discarded so far, oldest
+    /// first. Always empty for channels made without an overflow log.
+    pub fn overflow_drain(&self) -> Vec<T> {
+        let mut drained = Vec::new();
+
+        if let Some(log) = &self.inner.overflow_log {
+            while let Some(value) = log.pop() {
+                drained.push(value);
+            }
+        }
+
+        drained
+    }
+
+    /// Like `recv`, but gives up with `RecvTimeoutError::Timeout` once
+    /// `dur` elapses without a message.
+    pub fn recv_timeout(&self, dur: Duration) -> RecvTimeoutFuture<'_, T, B> {
+        RecvTimeoutFuture {
+            recv: self.recv(),
+            sleep: Box::pin(tokio::time::sleep(dur)),
+        }
+    }
+
+    /// Like `recv_timeout`, but against an absolute `deadline`, so a retry
+    /// loop can keep passing the same end time. A deadline already past
+    /// still takes a message that is waiting, without parking.
+    pub fn recv_deadline(&self, deadline: Instant) -> RecvTimeoutFuture<'_, T, B> {
+        RecvTimeoutFuture {
+            recv: self.recv(),
+            sleep: Box::pin(tokio::time::sleep_until(deadline.into())),
+        }
+    }
+}
+
+impl<T> Receiver<T, Open> {
+    /// A clone of the next message `recv` would return, leaving it queued.
+    /// Doesn't wake any senders, since nothing was freed. On a rendezvous
+    /// channel it only sees a value a sender is currently handing off.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        if self.inner.aborted() {
+            return None;
+        }
+
+        match &self.inner.rendezvous {
+            Some(rendezvous) => lock(rendezvous).slot.clone(),
+            None => self.inner.buffer.peek(),
+        }
+    }
+
+    /// Removes and returns the buffered messages `f` matches, in order,
+    /// without waiting. The rest stay queued in their original order.
+    pub fn drain_matching<F: FnMut(&T) -> bool>(&self, f: F) -> Vec<T> {
+        if self.inner.aborted() {
+            return Vec::new();
+        }
+
+        let matched = self.inner.buffer.extract_if(f);
+
+        if !matched.is_empty() {
+            self.inner.on_pop(matched.len());
+        }
+        matched
+    }
+
+    /// Like `recv`, but hands `f` the message in place instead of moving it
+    /// out, for `T`s too large to copy cheaply, and returns what `f` does.
+    /// The message is consumed once `f` returns, or if it panics.
+    ///
+    /// The buffer stays locked while `f` runs, stalling every sender and
+    /// receiver on the channel for that long, so keep `f` short, and never
+    /// use this channel from inside it: that would deadlock. The lock is
+    /// never held across an await.
+    ///
+    /// # Panics
+    /// On a rendezvous channel, which has no buffer to borrow from.
+    pub async fn recv_with<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        let inner = &self.inner;
+        assert!(
+            inner.rendezvous.is_none(),
+            "Rendezvous channels have no buffer to borrow from"
+        );
+
+        let mut waiter = Waiter::new(&inner.waiting_receivers);
+        let res = poll_fn(|cx| {
+            if inner.aborted() {
+                return Poll::Ready(None);
+            }
+            if let Some(front) = inner.buffer.front() {
+                return Poll::Ready(Some(RecvGuard {
+                    front: ManuallyDrop::new(front),
+                    inner,
+                }));
+            }
+            if inner.recv_closed() {
+                return Poll::Ready(None);
+            }
+
+            waiter.register(cx.waker());
+
+            if !inner.buffer.is_empty() || inner.recv_closed() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res.map(|front| f(&front))
+    }
+}
+
+impl<T, B> WeakSender<T, B> {
+    /// Returns a real `Sender` if at least one is still alive.
+    pub fn upgrade(&self) -> Option<Sender<T, Open, B>> {
+        let inner = self.inner.upgrade()?;
+
+        // Only count ourselves in while another sender still is: once the
+        // count has hit 0, receivers have been told the channel is closed
+        let mut senders = inner.senders.load(Ordering::Relaxed);
+        loop {
+            if senders == 0 {
+                return None;
+            }
+
+            match inner.senders.compare_exchange_weak(
+                senders,
+                senders + 1,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => senders = actual,
+            }
+        }
+
+        Some(Sender {
+            inner,
+            poll_state: Mutex::default(),
+            _state: PhantomData,
+        })
+    }
+}
+
+impl<T, B> NotifyHandle<T, B> {
+    /// Wakes every parked receiver. A no-op once the channel is gone.
+    pub fn notify(&self) {
+        if let Some(inner) = self.inner.upgrade() {
+            inner.wake_all_receivers();
+        }
+    }
+}
+
+impl<T, B> Clone for NotifyHandle<T, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, B> Clone for WeakSender<T, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<U, T, F: Fn(U) -> T, B: Buffer<T>> MappedSender<U, T, F, B> {
+    pub fn send(&self, value: U) -> SendFuture<'_, T, B> {
+        self.sender.send((self.f)(value))
+    }
+
+    pub fn try_send(&self, value: U) -> Result<(), TrySendError<T>> {
+        self.sender.try_send((self.f)(value))
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.sender.sender_count()
+    }
+
+    /// Unwraps the plain sender underneath.
+    pub fn into_inner(self) -> Sender<T, Open, B> {
+        self.sender
+    }
+}
+
+impl<U, T, F: Clone, B> Clone for MappedSender<U, T, F, B> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            f: self.f.clone(),
+            _input: PhantomData,
+        }
+    }
+}
+
+// By hand, so the state marker and the buffer needn't be `Debug`
+impl<T, S, B> std::fmt::Debug for Sender<T, S, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender")
+            .field("name", &self.inner.name)
+            .field("senders", &self.inner.sender_count())
+            .field("receivers", &self.inner.receiver_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, S, B> std::fmt::Debug for Receiver<T, S, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Receiver")
+            .field("name", &self.inner.name)
+            .field("senders", &self.inner.sender_count())
+            .field("receivers", &self.inner.receiver_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, S, B> Clone for Sender<T, S, B> {
+    fn clone(&self) -> Self {
+        // Cloning a live sender, so the count can't be at 0
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            inner: self.inner.clone(),
+            poll_state: Mutex::default(),
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<T, S, B> Clone for Receiver<T, S, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _receiver_ref: self._receiver_ref.clone(),
+            poll_state: Mutex::default(),
+            _state: PhantomData,
+        }
+    }
+}
+
+/// Debug builds only: calls the channel's `on_stall` hook, once, if the
+/// future owning it stays parked for `stall_timeout`.
+#[cfg(debug_assertions)]
+#[derive(Default)]
+enum StallWatchdog {
+    #[default]
+    Idle,
+    Armed(Pin<Box<tokio::time::Sleep>>),
+    Fired,
+}
+
+#[cfg(debug_assertions)]
+impl StallWatchdog {
+    fn poll<T, B: Buffer<T>>(
+        &mut self,
+        inner: &ChannelInner<T, B>,
+        operation: &'static str,
+        cx: &mut Context<'_>,
+    ) {
+        if let Self::Idle = self {
+            // Blocking callers may have no runtime to drive the timer
+            let (Some(timeout), Ok(_)) =
+                (inner.stall_timeout, tokio::runtime::Handle::try_current())
+            else {
+                return;
+            };
+            *self = Self::Armed(Box::pin(tokio::time::sleep(timeout)));
+        }
+
+        if let Self::Armed(sleep) = self
+            && sleep.as_mut().poll(cx).is_ready()
+        {
+            (inner.on_stall)(&inner.stall_report(operation));
+            *self = Self::Fired;
+        }
+    }
+}
+
+pub struct SendFuture<'a, T, B = RingBuffer<T>> {
+    sender: &'a Sender<T, Open, B>,
+    value: Option<T>,
+    ticket: Option<u64>, // rendezvous only: set once our value is in the slot
+    waiter: Waiter<'a>,
+    #[cfg(debug_assertions)]
+    watchdog: StallWatchdog,
+}
+
+impl<T, B: Buffer<T>> Sender<T, Open, B> {
+    fn poll_handoff(
+        &self,
+        rendezvous: &Mutex<Rendezvous<T>>,
+        value: &mut Option<T>,
+        ticket: &mut Option<u64>,
+        waiter: &mut Waiter<'_>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        let inner = &self.inner;
+        let mut state = lock(rendezvous);
+
+        match *ticket {
+            Some(ticket) if state.taken >= ticket => return Poll::Ready(Ok(())),
+            Some(_) if inner.send_closed() => {
+                // Untaken, so the slot still holds our value
+                let value = state.slot.take().expect("deposited value missing");
+                *ticket = None;
+                return Poll::Ready(Err(SendError::Closed(value)));
+            }
+            Some(_) => {}
+            // Checked again under the lock so nothing lands in the slot
+            // after `abort` has emptied it
+            None if state.slot.is_none() && state.ready_receivers > 0 && !inner.send_closed() => {
+                state.slot = value.take();
+                state.deposited += 1;
+                *ticket = Some(state.deposited);
+                inner.wake_n(1);
+            }
+            None => {}
+        }
+
+        // Park until a receiver is ready, or until it has taken our value.
+        // Registering under the rendezvous lock means neither wakeup is lost.
+        waiter.register(cx.waker());
+
+        // Dropping receivers don't take that lock, so check for them again
+        if inner.send_closed() {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+// The value is never pinned: `poll` only ever moves it, whole, into the
+// buffer or back out to the caller. So pinning the future says nothing about
+// where the value lives, even for `!Unpin` payloads.
+impl<T, B> Unpin for SendFuture<'_, T, B> {}
+
+impl<'a, T, B: Buffer<T>> Future for SendFuture<'a, T, B> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let this = self.get_mut();
+        #[cfg(feature = "tracing")]
+        let _span = this.sender.inner.poll_span("send").entered();
+        let res =
+            this.sender
+                .poll_send_with(&mut this.value, &mut this.ticket, &mut this.waiter, cx);
+
+        #[cfg(feature = "tracing")]
+        this.sender.inner.trace_poll(res.is_ready());
+        if res.is_ready() {
+            this.waiter.deregister();
+        }
+        #[cfg(debug_assertions)]
+        if res.is_pending() {
+            this.watchdog.poll(&this.sender.inner, "send", cx);
+        }
+
+        res
+    }
+}
+
+impl<T, B: Buffer<T>> Sender<T, Open, B> {
+    /// The body of a `send` poll. `ticket` and `waiter` are the pending
+    /// operation's state, kept by `SendFuture` or, for `poll_send`, by the
+    /// handle itself.
+    fn poll_send_with(
+        &self,
+        value: &mut Option<T>,
+        ticket: &mut Option<u64>,
+        waiter: &mut Waiter<'_>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        if self.inner.send_closed()
+            && let Some(value) = value.take()
+        {
+            return Poll::Ready(Err(SendError::Closed(value)));
+        }
+
+        if let Some(rendezvous) = &self.inner.rendezvous {
+            return self.poll_handoff(rendezvous, value, ticket, waiter, cx);
+        }
+
+        if let Some(res) = value.take() {
+            match self.inner.buffer.push(res) {
+                Ok(()) => {
+                    self.inner.on_push(1);
+                    Poll::Ready(Ok(()))
+                }
+                Err(rejected) if self.inner.overflow != OverflowPolicy::Block => {
+                    self.inner.handle_overflow(rejected);
+                    Poll::Ready(Ok(()))
+                }
+                Err(rejected) => {
+                    self.inner.record_full_rejection();
+                    let Err(rejected) = self.inner.spin_push(rejected) else {
+                        self.inner.on_push(1);
+                        return Poll::Ready(Ok(()));
+                    };
+
+                    waiter.register(cx.waker());
+                    *value = Some(rejected);
+
+                    // A receiver on another thread may have popped, or the
+                    // last one dropped, between our failed push and
+                    // registering; re-poll if so
+                    let inner = &self.inner;
+                    if !inner.buffer.is_full() || inner.send_closed() {
+                        cx.waker().wake_by_ref();
+                    }
+                    Poll::Pending
+                }
+            }
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A slot reserved by `Sender::reserve`. Dropping it unused hands the slot
+/// back to other senders.
+pub struct Permit<'a, T> {
+    sender: &'a Sender<T, Open>,
+    used: bool,
+}
+
+impl<'a, T> Permit<'a, T> {
+    /// Writes `value` into the reserved slot. Never waits.
+    pub fn send(mut self, value: T) {
+        self.sender.inner.buffer.push_reserved(value);
+        self.used = true;
+        self.sender.inner.on_push(1);
+    }
+}
+
+impl<'a, T> Drop for Permit<'a, T> {
+    fn drop(&mut self) {
+        if !self.used {
+            self.sender.inner.buffer.release_reservation();
+            self.sender.inner.wake_n_senders(1);
+        }
+    }
+}
+
+impl<T> OwnedSender<T> {
+    fn inner(&self) -> &UnsharedInner<T> {
+        // SAFETY: Not freed before this half is dropped
+        unsafe { self.inner.as_ref() }
+    }
+
+    /// Sends `value`, waiting while the buffer is full. Fails with
+    /// `SendError::Closed` once the receiver is gone.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let inner = self.inner();
+        let mut value = Some(value);
+
+        let mut waiter = Waiter::new(&inner.waiting_senders);
+        let res = poll_fn(|cx| {
+            let pending = value.take().expect("polled after completion");
+            if self.is_closed() {
+                return Poll::Ready(Err(SendError::Closed(pending)));
+            }
+
+            let Err(rejected) = inner.buffer.push(pending) else {
+                inner.waiting_receivers.wake(1);
+                return Poll::Ready(Ok(()));
+            };
+            value = Some(rejected);
+            waiter.register(cx.waker());
+
+            // The receiver may have popped or dropped before we registered
+            if !inner.buffer.is_full() || self.is_closed() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+
+    /// Sends without waiting, failing with `Full` if there is no room.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.is_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+
+        let inner = self.inner();
+        inner.buffer.push(value).map_err(TrySendError::Full)?;
+        inner.waiting_receivers.wake(1);
+
+        Ok(())
+    }
+
+    /// Whether the receiver is gone.
+    pub fn is_closed(&self) -> bool {
+        !self.inner().receiver_alive.load(Ordering::Acquire)
+    }
+}
+
+impl<T> OwnedReceiver<T> {
+    fn inner(&self) -> &UnsharedInner<T> {
+        // SAFETY: Not freed before this half is dropped
+        unsafe { self.inner.as_ref() }
+    }
+
+    fn sender_gone(&self) -> bool {
+        !self.inner().sender_alive.load(Ordering::Acquire)
+    }
+
+    /// The next message, waiting for one if the buffer is empty. `None`
+    /// once the sender is gone and the buffer is drained.
+    pub async fn recv(&self) -> Option<T> {
+        let inner = self.inner();
+
+        let mut waiter = Waiter::new(&inner.waiting_receivers);
+        let res = poll_fn(|cx| match self.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => {
+                waiter.register(cx.waker());
+
+                // A send or the sender's drop may have landed before we
+                // registered
+                if !inner.buffer.is_empty() || self.sender_gone() {
+                    cx.waker().wake_by_ref();
+                }
+                Poll::Pending
+            }
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+
+    /// Takes a message if one is ready, without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let inner = self.inner();
+
+        // Checked before popping, so a message sent just before the sender
+        // dropped is still found
+        let sender_gone = self.sender_gone();
+        match inner.buffer.pop() {
+            Some(value) => {
+                inner.waiting_senders.wake(1);
+                Ok(value)
+            }
+            None if sender_gone => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Whether the sender is gone and everything it sent was received.
+    pub fn is_closed(&self) -> bool {
+        self.sender_gone() && self.inner().buffer.is_empty()
+    }
+}
+
+impl<T> Drop for OwnedSender<T> {
+    fn drop(&mut self) {
+        // SAFETY: Called once, from the sender's drop
+        unsafe {
+            UnsharedInner::release(
+                self.inner,
+                |inner| &inner.sender_alive,
+                |inner| &inner.waiting_receivers,
+            );
+        }
+    }
+}
+
+impl<T> Drop for OwnedReceiver<T> {
+    fn drop(&mut self) {
+        // SAFETY: Called once, from the receiver's drop
+        unsafe {
+            UnsharedInner::release(
+                self.inner,
+                |inner| &inner.receiver_alive,
+                |inner| &inner.waiting_senders,
+            );
+        }
+    }
+}
+
+impl<T, B> Deref for PrimaryReceiver<T, B> {
+    type Target = Receiver<T, Open, B>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl<T, B> Drop for PrimaryReceiver<T, B> {
+    fn drop(&mut self) {
+        self.receiver.inner.close();
+    }
+}
+
+// The message `Receiver::recv_with` lends out. Dropping it, even while
+// unwinding out of the callback, consumes the message and frees its slot
+struct RecvGuard<'a, T> {
+    front: ManuallyDrop<FrontGuard<'a, T>>,
+    inner: &'a ChannelInner<T>,
+}
+
+impl<T> Deref for RecvGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.front
+    }
+}
+
+impl<T> Drop for RecvGuard<'_, T> {
+    fn drop(&mut self) {
+        // Unlock the buffer before waking senders that will push into it
+        // SAFETY: `front` is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self.front) };
+        self.inner.on_pop(1);
+    }
+}
+
+/// Slots reserved by `Sender::reserve_many`. Dropping it enqueues what was
+/// sent as one contiguous run and hands the unused slots back.
+pub struct PermitMany<'a, T> {
+    sender: &'a Sender<T, Open>,
+    unused: usize,
+    pending: Vec<T>, // held back so the burst lands in one go
+}
+
+impl<'a, T> PermitMany<'a, T> {
+    /// Adds `value` to the burst. Never waits.
+    ///
+    /// # Panics
+    /// If every reserved slot has already been used.
+    pub fn send(&mut self, value: T) {
+        assert!(self.unused > 0, "PermitMany has no reserved slots left");
+
+        self.unused -= 1;
+        self.pending.push(value);
+    }
+
+    /// Reserved slots not yet used by `send`.
+    pub fn remaining(&self) -> usize {
+        self.unused
+    }
+}
+
+impl<'a, T> Drop for PermitMany<'a, T> {
+    fn drop(&mut self) {
+        let inner = &self.sender.inner;
+        let count = self.pending.len();
+
+        if count > 0 {
+            inner
+                .buffer
+                .push_all_reserved(std::mem::take(&mut self.pending));
+            inner.on_push(count);
+        }
+        if self.unused > 0 {
+            inner.buffer.release_reservations(self.unused);
+            inner.wake_n_senders(self.unused);
+        }
+    }
+}
+
+pub struct SendTimeoutFuture<'a, T, B = RingBuffer<T>> {
+    send: SendFuture<'a, T, B>,
+    sleep: Pin<Box<tokio::time::Sleep>>,
+}
+
+impl<'a, T, B: Buffer<T>> Future for SendTimeoutFuture<'a, T, B> {
+    type Output = Result<(), SendTimeoutError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Poll::Ready(res) = Pin::new(&mut this.send).poll(cx) {
+            return Poll::Ready(res.map_err(|err| SendTimeoutError::Closed(err.into_inner())));
+        }
+
+        if this.sleep.as_mut().poll(cx).is_ready() {
+            // A rendezvous value already handed to a receiver can't be
+            // recalled, so that send simply runs to completion
+            if let Some(value) = this.send.value.take() {
+                this.send.waiter.deregister();
+                return Poll::Ready(Err(SendTimeoutError::Timeout(value)));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T, S, B> Drop for Sender<T, S, B> {
+    fn drop(&mut self) {
+        // Like a dropped `SendFuture`, pass on a wakeup `poll_send` got
+        let token = lock(&self.poll_state).token;
+        drop(Waiter::resume(&self.inner.waiting_senders, token));
+
+        // Decrement and check in one step: two senders checking first could
+        // each see the other alive, and then neither would wake receivers
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.on_senders_gone();
+        }
+    }
+}
+
+impl<T, S, B> Drop for Receiver<T, S, B> {
+    fn drop(&mut self) {
+        // Undo a pending `poll_recv` as dropping its `RecvFuture` would
+        let PollRecvState { token, ready } = *lock(&self.poll_state);
+        drop(Waiter::resume(&self.inner.waiting_receivers, token));
+        if ready && let Some(rendezvous) = &self.inner.rendezvous {
+            lock(rendezvous).ready_receivers -= 1;
+        }
+
+        // Same ordering concern as in `Sender`'s drop
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._receiver_ref) };
+
+        if self.inner.receiver_count() == 0 {
+            // Nothing will drain the buffer any more, so parked senders
+            // should re-poll and see the channel closed
+            self.inner.wake_all_flushers();
+            self.inner.wake_all_senders();
+        }
+    }
+}
+
+pub struct RecvFuture<'a, T, B = RingBuffer<T>> {
+    receiver: &'a Receiver<T, Open, B>,
+    ready: bool, // rendezvous only: counted in `ready_receivers`
+    waiter: Waiter<'a>,
+    #[cfg(debug_assertions)]
+    watchdog: StallWatchdog,
+}
+
+impl<T, B: Buffer<T>> Receiver<T, Open, B> {
+    fn poll_handoff(
+        &self,
+        rendezvous: &Mutex<Rendezvous<T>>,
+        waiter: &mut Waiter<'_>,
+        ready: &mut bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<T>> {
+        let inner = &self.inner;
+        let mut state = lock(rendezvous);
+
+        if let Some(val) = state.slot.take() {
+            state.taken += 1;
+            if *ready {
+                state.ready_receivers -= 1;
+                *ready = false;
+            }
+            drop(state);
+
+            inner.on_handoff();
+            return Poll::Ready(Some(val));
+        }
+
+        waiter.register(cx.waker());
+
+        if inner.recv_closed() {
+            waiter.deregister();
+            if *ready {
+                state.ready_receivers -= 1;
+                *ready = false;
+            }
+            return Poll::Ready(None);
+        }
+
+        if !*ready {
+            *ready = true;
+            state.ready_receivers += 1;
+            drop(state);
+
+            inner.wake_n_senders(1);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<'a, T, B: Buffer<T>> Future for RecvFuture<'a, T, B> {
+    type Output = Option<T>;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        #[cfg(feature = "tracing")]
+        let _span = this.receiver.inner.poll_span("recv").entered();
+        let res = this
+            .receiver
+            .poll_recv_with(&mut this.waiter, &mut this.ready, cx);
+
+        #[cfg(feature = "tracing")]
+        this.receiver.inner.trace_poll(res.is_ready());
+        if res.is_ready() {
+            this.waiter.deregister();
+        }
+        #[cfg(debug_assertions)]
+        if res.is_pending() {
+            this.watchdog.poll(&this.receiver.inner, "recv", cx);
+        }
+
+        res
+    }
+}
+
+impl<T, B: Buffer<T>> Receiver<T, Open, B> {
+    /// The body of a `recv` poll. `waiter` and `ready` are the pending
+    /// operation's state, kept by `RecvFuture` or, for `poll_recv`, by the
+    /// handle itself.
+    fn poll_recv_with(
+        &self,
+        waiter: &mut Waiter<'_>,
+        ready: &mut bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<T>> {
+        if let Some(rendezvous) = &self.inner.rendezvous {
+            return self.poll_handoff(rendezvous, waiter, ready, cx);
+        }
+
+        let buffer_empty = self.inner.buffer.is_empty();
+
+        if self.inner.aborted() || (self.inner.recv_closed() && buffer_empty) {
+            return Poll::Ready(None);
+        }
+        match self.inner.buffer.pop().or_else(|| self.inner.spin_pop()) {
+            Some(val) => {
+                self.inner.on_pop(1);
+                Poll::Ready(Some(val))
+            }
+            None => {
+                waiter.register(cx.waker());
+
+                // Check again after adding to queue!
+                if self.inner.recv_closed() && self.inner.buffer.is_empty() {
+                    // Senders died while we were adding to queue
+                    // Remove ourselves and return None
+                    waiter.deregister();
+                    return Poll::Ready(None);
+                }
+
+                // Same for a sender that pushed before we registered
+                if !self.inner.buffer.is_empty() {
+                    cx.waker().wake_by_ref();
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Waits on several receivers at once, resolving to the index of the first
+/// one to yield and what it yielded (`None` if that channel closed). Earlier
+/// receivers in the slice win ties.
+///
+/// # Panics
+/// If `receivers` is empty, since nothing could ever resolve the select.
+pub fn select_recv<'a, T, B: Buffer<T>>(
+    receivers: &[&'a Receiver<T, Open, B>],
+) -> SelectRecv<'a, T, B> {
+    assert!(
+        !receivers.is_empty(),
+        "select_recv needs at least one receiver"
+    );
+
+    SelectRecv {
+        recvs: receivers.iter().map(|receiver| receiver.recv()).collect(),
+    }
+}
+
+pub struct SelectRecv<'a, T, B = RingBuffer<T>> {
+    recvs: Vec<RecvFuture<'a, T, B>>,
+}
+
+impl<'a, T, B: Buffer<T>> Future for SelectRecv<'a, T, B> {
+    type Output = (usize, Option<T>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        for (index, recv) in this.recvs.iter_mut().enumerate() {
+            if let Poll::Ready(value) = Pin::new(recv).poll(cx) {
+                // Dropping the losers takes them out of their channels' queues
+                this.recvs.clear();
+                return Poll::Ready((index, value));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Fans several receivers in to one, made by `merge`.
+pub struct MergedReceiver<T, B = RingBuffer<T>> {
+    receivers: Vec<Receiver<T, Open, B>>,
+    start: usize, // where the next `recv` starts polling
+}
+
+/// Merges `receivers` into one consumption point. Each `recv` starts
+/// polling one receiver further along than the last, so a busy channel
+/// early in the list can't starve the later ones.
+pub fn merge<T, B: Buffer<T>>(receivers: Vec<Receiver<T, Open, B>>) -> MergedReceiver<T, B> {
+    MergedReceiver {
+        receivers,
+        start: 0,
+    }
+}
+
+impl<T, B: Buffer<T>> MergedReceiver<T, B> {
+    /// The first message available from any of the receivers. `None` once
+    /// every channel is closed and drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        let count = self.receivers.len();
+        let start = self.start;
+        self.start = (start + 1) % count.max(1);
+
+        let mut recvs: Vec<_> = (0..count)
+            .map(|i| Some(self.receivers[(start + i) % count].recv()))
+            .collect();
+
+        // Dropping `recvs` afterwards takes the losers out of their queues
+        poll_fn(|cx| {
+            for slot in &mut recvs {
+                let Some(recv) = slot else { continue };
+
+                if let Poll::Ready(res) = Pin::new(recv).poll(cx) {
+                    match res {
+                        Some(value) => return Poll::Ready(Some(value)),
+                        None => *slot = None,
+                    }
+                }
+            }
+
+            if recvs.iter().all(Option::is_none) {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+pub struct RecvResultFuture<'a, T, B = RingBuffer<T>> {
+    recv: RecvFuture<'a, T, B>,
+}
+
+impl<'a, T, B: Buffer<T>> Future for RecvResultFuture<'a, T, B> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().recv)
+            .poll(cx)
+            .map(|res| res.ok_or(RecvError::Disconnected))
+    }
+}
+
+/// Made by `Receiver::stream`.
+pub struct RecvStream<'a, T, B = RingBuffer<T>> {
+    recv: RecvFuture<'a, T, B>,
+    done: bool,
+}
+
+impl<'a, T, B: Buffer<T>> Stream for RecvStream<'a, T, B> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        let res = ready!(Pin::new(&mut this.recv).poll(cx));
+        match res {
+            // A fresh future for the next item, so its stall watchdog
+            // starts over
+            Some(_) => this.recv = this.recv.receiver.recv(),
+            None => this.done = true,
+        }
+
+        Poll::Ready(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+
+        (self.recv.receiver.inner.buffer.len(), None)
+    }
+}
+
+pub struct RecvTimeoutFuture<'a, T, B = RingBuffer<T>> {
+    recv: RecvFuture<'a, T, B>,
+    sleep: Pin<Box<tokio::time::Sleep>>,
+}
+
+impl<'a, T, B: Buffer<T>> Future for RecvTimeoutFuture<'a, T, B> {
+    type Output = Result<T, RecvTimeoutError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Poll::Ready(res) = Pin::new(&mut this.recv).poll(cx) {
+            return Poll::Ready(res.ok_or(RecvTimeoutError::Disconnected));
+        }
+
+        if this.sleep.as_mut().poll(cx).is_ready() {
+            // The recv above parked us; don't leave a dead waker behind
+            this.recv.waiter.deregister();
+            return Poll::Ready(Err(RecvTimeoutError::Timeout));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<'a, T, B> Drop for RecvFuture<'a, T, B> {
+    fn drop(&mut self) {
+        if self.ready
+            && let Some(rendezvous) = &self.receiver.inner.rendezvous
+        {
+            lock(rendezvous).ready_receivers -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{CountingWaker, poll_once};
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use test_strategy::proptest as async_proptest;
+    use tokio::task::JoinSet;
+
+    #[tokio::test]
+    async fn test_single_send_recv() {
+        let (tx, rx) = channel(8);
+        tx.send(3).await.unwrap();
+
+        if let Some(val) = rx.recv().await {
+            assert_eq!(val, 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multi_senders() {
+        let (tx, rx) = channel(4);
+        let tx_clone = tx.clone();
+
+        tx_clone.send(String::from("hello")).await.unwrap();
+        tx.send(String::from("World")).await.unwrap();
+
+        if let Some(res) = rx.recv().await {
+            assert!(&res == "hello");
+        }
+
+        if let Some(res) = rx.recv().await {
+            assert!(&res == "World");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_senders_drop() {
+        let (tx, rx) = channel(4);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+
+        drop(tx); // Last sender dropped
+
+        assert_eq!(rx.recv().await, Some(1)); // Should drain
+        assert_eq!(rx.recv().await, Some(2)); // Should drain
+        assert_eq!(rx.recv().await, None); // Should close
+    }
+
+    #[tokio::test]
+    async fn test_mpmc_stress() {
+        let (tx, rx) = channel::<u32>(64);
+        let sent = Arc::new(Mutex::new(HashSet::<u32>::new()));
+        let received = Arc::new(Mutex::new(HashSet::<u32>::new()));
+
+        let mut all_handlers = vec![];
+
+        // 4 senders, each sends 0-2499 with offset
+        // 4 receivers, collect all messages
+
+        for i in 0..4 {
+            let tx = tx.clone();
+            let sent = sent.clone();
+            let handler = tokio::spawn(async move {
+                for j in 1..=2500 {
+                    let res = (j + 2500 * i) as u32;
+                    tx.send(res).await.unwrap();
+                    sent.lock().unwrap().insert(res);
+                }
+            });
+            all_handlers.push(handler);
+        }
+
+        drop(tx);
+
+        for _ in 1..=4 {
+            let rx = rx.clone();
+            let received = received.clone();
+            let handler = tokio::spawn(async move {
+                while let Some(got) = rx.recv().await {
+                    received.lock().unwrap().insert(got);
+                }
+            });
+            all_handlers.push(handler);
+        }
+
+        for handle in all_handlers {
+            let _ = handle.await;
+        }
+
+        for val in sent.lock().unwrap().iter() {
+            assert_eq!(val, received.lock().unwrap().get(val).unwrap())
+        }
+        assert_eq!(sent.lock().unwrap().len(), 10000);
+    }
+
+    #[tokio::test]
+    async fn test_simple_channel_creation() {
+        println!("Before channel creation");
+        let (tx, rx) = channel::<u8>(2);
+        println!("After channel creation");
+        drop(tx);
+        drop(rx);
+        println!("Test complete");
+    }
+
+    #[test]
+    fn test_spinning_recv_takes_a_late_message_without_parking() {
+        let (tx, rx) = channel_with_config::<u32>(ChannelConfig {
+            spin_before_park: u32::MAX,
+            ..ChannelConfig::new(2)
+        });
+        let waker = CountingWaker::new();
+
+        let sender = thread::spawn(move || tx.try_send(1).unwrap());
+        let mut recv = pin!(rx.recv());
+        assert_eq!(poll_once(&mut recv, &waker.waker()), Poll::Ready(Some(1)));
+        assert_eq!(rx.waiting_receivers_len(), 0);
+        assert_eq!(waker.count(), 0);
+        sender.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let (tx, rx) = channel::<u8>(4);
+        tx.send(5).await.unwrap();
+        tx.send(3).await.unwrap();
+        tx.send(8).await.unwrap();
+
+        let tx_clone = tx.clone();
+        let woken = CountingWaker::new();
+        let mut third_try = tx_clone.send(3);
+        assert!(poll_once(&mut third_try, &woken.waker()).is_pending());
+        assert_eq!(tx.waiting_senders_len(), 1);
+
+        // Another sender leaving doesn't wake or fail the parked one
+        drop(tx);
+        assert_eq!(woken.count(), 0);
+
+        assert_eq!(rx.recv().await, Some(5));
+        assert_eq!(woken.count(), 1);
+        assert_eq!(
+            poll_once(&mut third_try, &woken.waker()),
+            Poll::Ready(Ok(()))
+        );
+    }
+
+    #[ignore]
+    #[async_proptest(async = "tokio")]
+    async fn channel_preserves_all_messages(
+        #[strategy(prop::sample::select(vec![4usize, 8, 16, 32, 64]))] capacity: usize,
+        #[strategy(1usize..=1000)] num_messages: usize,
+    ) {
+        // Send num_messages through channel with capacity
+        // Receive all of them
+        // Assert: all messages arrived, none lost, none duplicated
+
+        let sent = Arc::new(Mutex::new(HashSet::<String>::new()));
         let received = Arc::new(Mutex::new(HashSet::<String>::new()));
 
-        let (tx, rx) = channel::<String>(capacity);
-        let mut set = JoinSet::new();
+        let (tx, rx) = channel::<String>(capacity);
+        let mut set = JoinSet::new();
+
+        let recv_clone = received.clone();
+
+        let sent_clone = sent.clone();
+        let tx_clone = tx.clone();
+        set.spawn(async move {
+            for j in 1..=num_messages {
+                let msg = format!("Test {j}");
+                tx_clone.send(msg.clone()).await.unwrap();
+                sent_clone.lock().unwrap().insert(msg);
+            }
+        });
+
+        drop(tx);
+
+        set.spawn(async move {
+            while let Some(got) = rx.recv().await {
+                recv_clone.lock().unwrap().insert(got);
+            }
+        });
+
+        while let Some(result) = set.join_next().await {
+            result.unwrap();
+        }
+
+        for val in sent.lock().unwrap().iter() {
+            let guard = received.lock().unwrap();
+            prop_assert_eq!(val, guard.get(val).unwrap());
+        }
+        prop_assert_eq!(sent.lock().unwrap().len(), num_messages);
+    }
+
+    #[tokio::test]
+    async fn test_drop_wakes_receivers() {
+        let (tx, rx) = channel::<u32>(4);
+
+        // Spawn 2 receivers that will wait
+        let rx1 = rx.clone();
+        let h1 = tokio::spawn(async move {
+            println!("Receiver 1 starting");
+            let result = rx1.recv().await;
+            println!("Receiver 1 got: {:?}", result);
+            result
+        });
+
+        let rx2 = rx.clone();
+        let h2 = tokio::spawn(async move {
+            println!("Receiver 2 starting");
+            let result = rx2.recv().await;
+            println!("Receiver 2 got: {:?}", result);
+            result
+        });
+
+        // Let receivers start waiting
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+        println!("Dropping all senders");
+        drop(tx);
+
+        // Both should complete with None
+        assert_eq!(h1.await.unwrap(), None);
+        assert_eq!(h2.await.unwrap(), None);
+        println!("Test passed!");
+    }
+
+    #[tokio::test]
+    async fn test_sender_receiver_counts() {
+        let (tx, rx) = channel::<u32>(4);
+        assert_eq!(tx.sender_count(), 1);
+        assert_eq!(rx.receiver_count(), 1);
+
+        let tx2 = tx.clone();
+        let rx2 = rx.clone();
+        let rx3 = rx.clone();
+        assert_eq!(rx.sender_count(), 2);
+        assert_eq!(tx.receiver_count(), 3);
+
+        drop(tx2);
+        drop(rx3);
+        assert_eq!(rx2.sender_count(), 1);
+        assert_eq!(tx.receiver_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_wake_n_wakes_parked_receivers() {
+        let (tx, rx) = channel::<u32>(4);
+
+        let mut set = JoinSet::new();
+        for _ in 0..2 {
+            let rx = rx.clone();
+            set.spawn(async move { rx.recv().await });
+        }
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert_eq!(tx.inner.waiting_receivers.len(), 2);
+
+        // Enqueue behind the futures' backs, then wake both at once
+        tx.inner.buffer.push(1).unwrap();
+        tx.inner.buffer.push(2).unwrap();
+        tx.inner.wake_n(2);
+
+        let mut got = vec![];
+        while let Some(res) = set.join_next().await {
+            got.push(res.unwrap().unwrap());
+        }
+        got.sort();
+        assert_eq!(got, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_send_adaptive_times_out_faster_when_stalled() {
+        let (fast_tx, fast_rx) = channel::<u32>(2);
+        for i in 0..8 {
+            fast_tx.send(i).await.unwrap();
+            fast_rx.recv().await.unwrap();
+        }
+        let fast_timeout = fast_tx.adaptive_timeout();
+
+        let (stalled_tx, _stalled_rx) = channel::<u32>(2);
+        stalled_tx.send(0).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let stalled_timeout = stalled_tx.adaptive_timeout();
+
+        assert!(stalled_timeout < fast_timeout);
+
+        let start = Instant::now();
+        let result = stalled_tx.send_adaptive(1).await;
+        assert_eq!(result, Err(SendError::BufferFull(1)));
+        assert!(start.elapsed() < fast_timeout);
+    }
+
+    #[tokio::test]
+    async fn test_rendezvous_send_waits_for_receiver() {
+        let (tx, rx) = channel::<u32>(0);
+        let handle = tokio::spawn(async move { tx.send(7).await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handle.is_finished());
+        assert!(rx.inner.buffer.is_empty());
+
+        assert_eq!(rx.recv().await, Some(7));
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rendezvous_lock_step() {
+        let (tx, rx) = channel::<u32>(0);
+        let producer = tokio::spawn(async move {
+            for i in 0..100 {
+                tx.send(i).await.unwrap();
+            }
+        });
+
+        for i in 0..100 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        assert_eq!(rx.recv().await, None);
+        producer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recv_timeout() {
+        let (tx, rx) = channel::<u32>(4);
+
+        let res = rx.recv_timeout(Duration::from_millis(20)).await;
+        assert_eq!(res, Err(RecvTimeoutError::Timeout));
+        assert!(rx.inner.waiting_receivers.is_empty());
+
+        tx.send(1).await.unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_millis(20)).await, Ok(1));
+
+        drop(tx);
+        let res = rx.recv_timeout(Duration::from_millis(20)).await;
+        assert_eq!(res, Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn test_recv_many_timeout() {
+        let (tx, rx) = channel::<u32>(8);
+        let mut out = vec![];
+
+        let count = rx
+            .recv_many_timeout(&mut out, 4, Duration::from_millis(20))
+            .await;
+        assert_eq!(count, 0);
+        assert!(rx.inner.waiting_receivers.is_empty());
+
+        let producer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            for i in 0..6 {
+                tx.send(i).await.unwrap();
+            }
+            tx
+        });
+        let tx = producer.await.unwrap();
+
+        let count = rx
+            .recv_many_timeout(&mut out, 4, Duration::from_secs(5))
+            .await;
+        assert_eq!((count, out.as_slice()), (4, &[0, 1, 2, 3][..]));
+
+        drop(tx);
+        out.clear();
+        let count = rx
+            .recv_many_timeout(&mut out, 4, Duration::from_secs(5))
+            .await;
+        assert_eq!((count, out.as_slice()), (2, &[4, 5][..]));
+        let count = rx
+            .recv_many_timeout(&mut out, 4, Duration::from_secs(5))
+            .await;
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_recv_deadline() {
+        let (tx, rx) = channel::<u32>(4);
+        let deadline = Instant::now() + Duration::from_millis(20);
+
+        assert_eq!(
+            rx.recv_deadline(deadline).await,
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert!(Instant::now() >= deadline);
+
+        // Past deadlines still hand out what is already there
+        tx.send(1).await.unwrap();
+        assert_eq!(rx.recv_deadline(deadline).await, Ok(1));
+        assert_eq!(
+            rx.recv_deadline(deadline).await,
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert!(rx.inner.waiting_receivers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resize_wakes_parked_sender() {
+        let (tx, rx) = channel::<u32>(2);
+        tx.send(1).await.unwrap();
+
+        let tx_clone = tx.clone();
+        let parked = tokio::spawn(async move { tx_clone.send(2).await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!parked.is_finished());
+
+        tx.resize(8);
+        parked.await.unwrap().unwrap();
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout_returns_value() {
+        let (tx, rx) = channel::<String>(2);
+        tx.send(String::from("first")).await.unwrap();
+
+        let res = tx
+            .send_timeout(String::from("second"), Duration::from_millis(20))
+            .await;
+        assert_eq!(res, Err(SendTimeoutError::Timeout(String::from("second"))));
+        assert!(tx.inner.waiting_senders.is_empty());
+
+        assert_eq!(rx.recv().await.as_deref(), Some("first"));
+        let res = tx
+            .send_timeout(String::from("third"), Duration::from_millis(20))
+            .await;
+        assert_eq!(res, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_log_captures_evicted() {
+        let (tx, rx) = channel_with_overflow_log::<u32>(4, OverflowPolicy::DropOldest, 8);
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(rx.overflow_drain(), vec![1, 2]);
+        for i in 3..=5 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+
+        let (tx, rx) = channel_with_overflow_log::<u32>(4, OverflowPolicy::DropNewest, 8);
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(rx.overflow_drain(), vec![4, 5]);
+        assert_eq!(rx.recv().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_log_drop_oldest_with_room_reserved() {
+        let (tx, rx) = channel_with_overflow_log::<u32>(2, OverflowPolicy::DropOldest, 4);
+        let permit = tx.try_reserve().unwrap();
+
+        // The only slot is reserved and there is nothing to evict, so the
+        // new message is the one dropped
+        tx.send(1).await.unwrap();
+        assert_eq!(tx.metrics().total_sent, 0);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(rx.overflow_drain(), vec![1]);
+
+        permit.send(2);
+        assert_eq!(rx.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_interop_with_async() {
+        let (tx, rx) = channel::<u32>(4);
+
+        // Blocked thread sender, async receiver
+        let tx_clone = tx.clone();
+        let producer = std::thread::spawn(move || {
+            for i in 0..100 {
+                tx_clone.send_blocking(i).unwrap();
+            }
+        });
+        for i in 0..100 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        producer.join().unwrap();
+
+        // Async sender, blocked thread receiver
+        let rx_clone = rx.clone();
+        let consumer = std::thread::spawn(move || {
+            (0..100)
+                .map(|_| rx_clone.recv_blocking().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for i in 0..100 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(
+            tokio::task::spawn_blocking(move || consumer.join().unwrap())
+                .await
+                .unwrap(),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_weak_sender_does_not_keep_channel_open() {
+        let (tx, rx) = channel::<u32>(4);
+        let weak = tx.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        upgraded.send(1).await.unwrap();
+        drop(upgraded);
+        assert_eq!(rx.sender_count(), 1);
+
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_upgrades_cannot_revive_channel() {
+        for _ in 0..200 {
+            let (tx, rx) = channel::<u32>(4);
+            let weaks = [tx.downgrade(), tx.downgrade()];
+            drop(tx);
+            assert_eq!(rx.sender_count(), 0);
+
+            let barrier = std::sync::Barrier::new(2);
+            thread::scope(|scope| {
+                for weak in &weaks {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        barrier.wait();
+                        assert!(weak.upgrade().is_none());
+                    });
+                }
+            });
+            assert_eq!(rx.sender_count(), 0);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_per_sender_fifo_across_receivers() {
+        let (tx, rx) = channel::<(usize, u32)>(8);
+        let mut producers = JoinSet::new();
+        let mut consumers = JoinSet::new();
+
+        for id in 0..2 {
+            let tx = tx.clone();
+            producers.spawn(async move {
+                for seq in 0..500 {
+                    tx.send((id, seq)).await.unwrap();
+                }
+            });
+        }
+        drop(tx);
+
+        for _ in 0..3 {
+            let rx = rx.clone();
+            consumers.spawn(async move {
+                let mut got = vec![];
+                while let Some(msg) = rx.recv().await {
+                    got.push(msg);
+                }
+                got
+            });
+        }
+
+        producers.join_all().await;
+        let mut total = 0;
+        for got in consumers.join_all().await {
+            total += got.len();
+            for id in 0..2 {
+                let seqs: Vec<_> = got.iter().filter(|m| m.0 == id).map(|m| m.1).collect();
+                assert!(seqs.windows(2).all(|w| w[0] < w[1]));
+            }
+        }
+        assert_eq!(total, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_try_send_returns_value() {
+        // Deliberately not Clone, like a file handle
+        #[derive(Debug, PartialEq)]
+        struct Handle(u32);
+
+        let (tx, rx) = channel::<Handle>(2);
+        tx.try_send(Handle(1)).unwrap();
+
+        let err = tx.try_send(Handle(2)).unwrap_err();
+        assert_eq!(err.to_string(), "Buffer is full");
+        assert_eq!(err.into_inner(), Handle(2));
+
+        drop(rx);
+        assert_eq!(tx.try_send(Handle(3)), Err(TrySendError::Closed(Handle(3))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_send_all_or_cancel_is_atomic() {
+        let (tx, rx) = channel::<u32>(8);
+
+        let producer = tokio::spawn(async move {
+            for batch in 0..200 {
+                let items = (0..3).map(|i| batch * 3 + i);
+                tx.send_all_or_cancel(items).await.unwrap();
+            }
+        });
+
+        for batch in 0..200 {
+            // Only whole batches ever sit in the buffer between our pops
+            assert_eq!(rx.inner.buffer.len() % 3, 0);
+            for i in 0..3 {
+                assert_eq!(rx.recv().await, Some(batch * 3 + i));
+            }
+        }
+        producer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_all_or_cancel_rejects_oversized_batch() {
+        let (tx, rx) = channel::<u32>(4);
+
+        let err = tx.send_all_or_cancel(0..4).await.unwrap_err();
+        assert_eq!(err.into_inner(), vec![0, 1, 2, 3]);
+        assert!(rx.inner.buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recv_many() {
+        let (tx, rx) = channel::<u32>(4);
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+
+        let mut parked = JoinSet::new();
+        for i in 3..5 {
+            let tx = tx.clone();
+            parked.spawn(async move { tx.send(i).await });
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut out = vec![];
+        assert_eq!(rx.recv_many(&mut out, 3).await, 3);
+        assert_eq!(out, vec![0, 1, 2]);
+
+        // Both parked senders were woken by the batch
+        while let Some(res) = parked.join_next().await {
+            res.unwrap().unwrap();
+        }
+
+        out.clear();
+        assert_eq!(rx.recv_many(&mut out, 10).await, 2);
+        out.sort();
+        assert_eq!(out, vec![3, 4]);
+
+        drop(tx);
+        assert_eq!(rx.recv_many(&mut out, 10).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_receiver_wakeups_are_fair() {
+        let (tx, rx) = channel::<u32>(64);
+        let mut set = JoinSet::new();
+
+        for _ in 0..4 {
+            let rx = rx.clone();
+            set.spawn(async move {
+                let mut count = 0;
+                while rx.recv().await.is_some() {
+                    count += 1;
+                }
+                count
+            });
+        }
+
+        for i in 0..400 {
+            tx.send(i).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        drop(tx);
+
+        // A fair share is 100 each; allow generous scheduling slack
+        for count in set.join_all().await {
+            assert!((50..=150).contains(&count), "unfair distribution: {count}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notify_handle_repolls_receiver() {
+        let (tx, rx) = channel::<u32>(4);
+        let notify = rx.notify_handle();
+
+        let rx_clone = rx.clone();
+        let parked = tokio::spawn(async move { rx_clone.recv().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // A nudge on an empty channel just parks the receiver again
+        notify.notify();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!parked.is_finished());
+        assert_eq!(rx.inner.waiting_receivers.len(), 1);
+
+        // Enqueue without the usual wakeup; only the nudge reveals it
+        tx.inner.buffer.push(5).unwrap();
+        notify.notify();
+        assert_eq!(parked.await.unwrap(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let config = |overflow| ChannelConfig {
+            overflow,
+            ..ChannelConfig::new(4)
+        };
+
+        let (tx, rx) = channel_with_config::<u32>(config(OverflowPolicy::DropNewest));
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(rx.recv_many(&mut vec![], 10).await, 3);
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(rx.recv().await, Some(1));
+
+        let (tx, rx) = channel_with_config::<u32>(config(OverflowPolicy::DropOldest));
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(rx.recv().await, Some(3));
+
+        let (tx, _rx) = channel_with_config::<u32>(config(OverflowPolicy::Block));
+        for i in 1..=3 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(tx.try_send(4), Err(TrySendError::Full(4)));
+    }
+
+    #[test]
+    fn test_poll_send_and_poll_recv_on_handles() {
+        let (tx, rx) = channel::<u32>(2);
+        let (send_woken, recv_woken) = (CountingWaker::new(), CountingWaker::new());
+        let (send_waker, recv_waker) = (send_woken.waker(), recv_woken.waker());
+        let mut send_cx = Context::from_waker(&send_waker);
+        let mut recv_cx = Context::from_waker(&recv_waker);
+
+        assert!(rx.poll_recv(&mut recv_cx).is_pending());
+        assert_eq!(rx.waiting_receivers_len(), 1);
+        // Polling again keeps the one registration
+        assert!(rx.poll_recv(&mut recv_cx).is_pending());
+        assert_eq!(rx.waiting_receivers_len(), 1);
+
+        let mut value = Some(1);
+        assert_eq!(tx.poll_send(&mut value, &mut send_cx), Poll::Ready(Ok(())));
+        assert_eq!(value, None);
+        assert_eq!(recv_woken.count(), 1);
+
+        let mut value = Some(2);
+        assert!(tx.poll_send(&mut value, &mut send_cx).is_pending());
+        assert_eq!((value, tx.waiting_senders_len()), (Some(2), 1));
+
+        assert_eq!(rx.poll_recv(&mut recv_cx), Poll::Ready(Some(1)));
+        assert_eq!(send_woken.count(), 1);
+        assert_eq!(tx.poll_send(&mut value, &mut send_cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.poll_recv(&mut recv_cx), Poll::Ready(Some(2)));
+
+        // A handle dropped while registered leaves the queue
+        assert!(rx.poll_recv(&mut recv_cx).is_pending());
+        drop(rx.clone());
+        assert_eq!(rx.waiting_receivers_len(), 1);
+        let parked = rx.clone();
+        assert!(parked.poll_recv(&mut recv_cx).is_pending());
+        drop(parked);
+        assert_eq!(rx.waiting_receivers_len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unshared_channel_closes_with_either_half() {
+        let (tx, rx) = channel_unshared::<u32>(4);
+        tx.send(1).await.unwrap();
+        tx.try_send(2).unwrap();
+        drop(tx);
+
+        assert!(!rx.is_closed());
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert!(rx.is_closed());
+        assert_eq!(rx.recv().await, None);
+
+        let (tx, rx) = channel_unshared::<u32>(4);
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+    }
+
+    #[test]
+    fn test_unshared_drop_wakes_the_parked_half() {
+        let (tx, rx) = channel_unshared::<String>(2);
+        let waker = CountingWaker::new();
+
+        tx.try_send(String::from("queued")).unwrap();
+        let mut send = Box::pin(tx.send(String::from("parked")));
+        assert!(poll_once(&mut send, &waker.waker()).is_pending());
+
+        drop(rx);
+        assert_eq!(waker.count(), 1);
+        assert_eq!(
+            poll_once(&mut send, &waker.waker()),
+            Poll::Ready(Err(SendError::Closed(String::from("parked"))))
+        );
+    }
+
+    #[test]
+    fn test_try_reserve_fails_fast_and_releases_on_drop() {
+        let (tx, rx) = channel::<u32>(2);
+
+        let permit = tx.try_reserve().unwrap();
+        assert_eq!(tx.try_reserve().err(), Some(SendError::BufferFull(())));
+        assert_eq!(tx.try_send(1), Err(TrySendError::Full(1)));
+
+        drop(permit);
+        tx.try_reserve().unwrap().send(2);
+        assert_eq!(rx.try_recv(), Ok(2));
+
+        rx.close();
+        assert_eq!(tx.try_reserve().err(), Some(SendError::Closed(())));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_holds_slot_until_permit_used() {
+        let (tx, rx) = channel::<u32>(4);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+
+        let permit = tx.reserve().await.unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        drop(permit);
+        let permit = tx.reserve().await.unwrap();
+        permit.send(3);
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_select_recv_takes_first_ready() {
+        let (tx1, rx1) = channel::<u32>(4);
+        let (tx2, rx2) = channel::<u32>(4);
+
+        let handle = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            tx2.send(7).await.unwrap();
+            tx2
+        });
+
+        assert_eq!(select_recv(&[&rx1, &rx2]).await, (1, Some(7)));
+        assert!(rx1.inner.waiting_receivers.is_empty());
+
+        let _tx2 = handle.await.unwrap();
+        drop(tx1);
+        assert_eq!(select_recv(&[&rx1, &rx2]).await, (0, None));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one receiver")]
+    fn test_select_recv_rejects_no_receivers() {
+        drop(select_recv::<u32, RingBuffer<u32>>(&[]));
+    }
+
+    #[test]
+    fn test_send_blocking_survives_spurious_unpark() {
+        let (tx, rx) = channel::<u32>(2);
+        tx.try_send(1).unwrap();
+
+        let producer = std::thread::spawn(move || tx.send_blocking(2));
+        for _ in 0..10 {
+            producer.thread().unpark();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert!(!producer.is_finished());
+        assert_eq!(rx.inner.buffer.len(), 1);
+
+        assert_eq!(rx.recv_blocking(), Some(1));
+        producer.join().unwrap().unwrap();
+        assert_eq!(rx.recv_blocking(), Some(2));
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_stalled_send_reports_channel_state() {
+        static REPORTS: Mutex<Vec<StallReport>> = Mutex::new(Vec::new());
+        assert_eq!(ChannelConfig::new(2).stall_timeout, None);
+
+        let (tx, _rx) = channel_with_config::<u32>(ChannelConfig {
+            stall_timeout: Some(Duration::from_millis(20)),
+            on_stall: |report| REPORTS.lock().unwrap().push(report.clone()),
+            ..ChannelConfig::new(2)
+        });
+        tx.send(1).await.unwrap();
+
+        let stalled = tokio::time::timeout(Duration::from_millis(100), tx.send(2)).await;
+        assert!(stalled.is_err());
+
+        assert_eq!(
+            *REPORTS.lock().unwrap(),
+            [StallReport {
+                operation: "send",
+                len: 1,
+                capacity: 2,
+                waiting_senders: 1,
+                waiting_receivers: 0,
+                senders: 1,
+                receivers: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_try_iter_and_iter_blocking() {
+        let (tx, rx) = channel::<u32>(8);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        for i in 0..3 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+
+        let producer = std::thread::spawn(move || {
+            for i in 3..6 {
+                tx.send_blocking(i).unwrap();
+            }
+        });
+        assert_eq!(rx.iter_blocking().collect::<Vec<_>>(), [3, 4, 5]);
+        producer.join().unwrap();
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_try_error_predicates() {
+        let (tx, rx) = channel::<u32>(2);
+        assert!(rx.try_recv().unwrap_err().is_empty());
+
+        tx.try_send(1).unwrap();
+        let full = tx.try_send(2).unwrap_err();
+        assert!(full.is_full() && !full.is_closed());
+        assert_eq!(full.into_inner(), 2);
+
+        rx.close();
+        assert!(tx.try_send(3).unwrap_err().is_closed());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(rx.try_recv().unwrap_err().is_disconnected());
+    }
+
+    #[test]
+    fn test_dropped_futures_leave_wait_queues() {
+        let (tx, rx) = channel::<u32>(2);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(rx.inner.waiting_receivers.len(), 1);
+        drop(recv);
+        assert!(rx.inner.waiting_receivers.is_empty());
+
+        tx.try_send(1).unwrap();
+        let mut send = Box::pin(tx.send(2));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(tx.inner.waiting_senders.len(), 1);
+        drop(send);
+        assert!(tx.inner.waiting_senders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_recv_keeps_message() {
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // Woken by the send, but dropped before polling again
+        let (tx, rx) = channel::<u32>(4);
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        tx.try_send(1).unwrap();
+        drop(recv);
+        assert_eq!(rx.recv().await, Some(1));
+
+        // Losing a `select!` to a branch that is already ready
+        tx.try_send(2).unwrap();
+        tokio::select! {
+            biased;
+            _ = std::future::ready(()) => {}
+            _ = rx.recv() => unreachable!(),
+        }
+        assert_eq!(rx.recv().await, Some(2));
+
+        // A rendezvous value deposited for the dropped receiver
+        let (tx, rx) = channel::<u32>(0);
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        let mut send = Box::pin(tx.send(3));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        drop(recv);
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(send.await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_unit_signal_channel() {
+        let (tx, rx) = channel::<()>(2);
+
+        let waiter = tokio::spawn(async move {
+            let mut signals = 0;
+            while rx.recv().await.is_some() {
+                signals += 1;
+            }
+            signals
+        });
+
+        for _ in 0..10 {
+            tx.send(()).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(waiter.await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_recv_with_consumes_the_message() {
+        let (tx, rx) = channel::<[u8; 4096]>(2);
+        tx.send([7; 4096]).await.unwrap();
+
+        // Parks until the sender, freed once the callback returns, gets through
+        let parked = tokio::spawn(async move {
+            tx.send([8; 4096]).await.unwrap();
+            tx.send([9; 4096]).await.unwrap();
+        });
+
+        assert_eq!(rx.recv_with(|message| message[0]).await, Some(7));
+        assert_eq!(rx.recv_with(|message| message[0]).await, Some(8));
+        parked.await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap()[0], 9);
+        assert_eq!(rx.recv_with(|message| message[0]).await, None);
+        assert_eq!(rx.metrics().total_received, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rendezvous_try_send_needs_parked_receiver() {
+        let (tx, rx) = channel::<u32>(0);
+        assert_eq!(tx.try_send(1), Err(TrySendError::Full(1)));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+
+        tx.try_send(2).unwrap();
+        // The slot is taken until that receiver collects it
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(recv.await, Some(2));
+
+        assert_eq!(tx.try_send(4), Err(TrySendError::Full(4)));
+        assert_eq!(
+            tx.metrics(),
+            ChannelMetrics {
+                total_sent: 1,
+                total_received: 1,
+                total_full_rejections: 3,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_survives_panic_holding_lock() {
+        let (tx, rx) = channel::<u32>(4);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+
+        // Panicking in the callback poisons the buffer lock
+        let borrower = rx.clone();
+        let crashed = tokio::spawn(async move {
+            borrower
+                .recv_with(|_| panic!("worker crashed mid-message"))
+                .await
+        });
+        assert!(crashed.await.unwrap_err().is_panic());
+
+        // The lent message was consumed as the callback unwound
+        tx.send(3).await.unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+    }
+
+    #[test]
+    fn test_recv_leaving_data_wakes_next_receiver() {
+        let (tx, rx) = channel::<u32>(4);
+        let (first_woken, second_woken) = (CountingWaker::new(), CountingWaker::new());
+
+        let mut first = rx.recv();
+        let mut second = rx.recv();
+        assert!(poll_once(&mut first, &first_woken.waker()).is_pending());
+        assert!(poll_once(&mut second, &second_woken.waker()).is_pending());
+
+        // One wakeup for two messages, as when a second push's wakeup went
+        // to a receiver that has since left
+        tx.try_send(1).unwrap();
+        tx.inner.buffer.push(2).unwrap();
+        assert_eq!((first_woken.count(), second_woken.count()), (1, 0));
+
+        assert_eq!(poll_once(&mut first, Waker::noop()), Poll::Ready(Some(1)));
+        assert_eq!(second_woken.count(), 1);
+        assert_eq!(poll_once(&mut second, Waker::noop()), Poll::Ready(Some(2)));
+    }
+
+    #[test]
+    fn test_expected_waiters_presizes_queues() {
+        let (tx, _rx) = channel_with_config::<u32>(ChannelConfig {
+            expected_waiters: 100,
+            ..ChannelConfig::new(4)
+        });
+
+        for list in [&tx.inner.waiting_senders, &tx.inner.waiting_receivers] {
+            assert!(lock(&list.queue).waiters.capacity() >= 100);
+        }
+        assert_eq!(lock(&tx.inner.waiting_flushers.queue).waiters.capacity(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_matching() {
+        let (tx, rx) = channel::<u32>(8);
+        for i in 0..6 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(rx.drain_matching(|v| v % 2 == 1), [1, 3, 5]);
+        assert_eq!(rx.metrics().total_received, 3);
+        for expected in [0, 2, 4] {
+            assert_eq!(rx.recv().await, Some(expected));
+        }
+    }
 
-        let recv_clone = received.clone();
+    #[tokio::test]
+    async fn test_send_all_waits_between_items() {
+        let (tx, rx) = channel::<u32>(2);
 
-        let sent_clone = sent.clone();
-        let tx_clone = tx.clone();
-        set.spawn(async move {
-            for j in 1..=num_messages {
-                let msg = format!("Test {j}");
-                tx_clone.send(msg.clone()).await.unwrap();
-                sent_clone.lock().unwrap().insert(msg);
+        // Twice the buffer's room, so the sender parks along the way
+        let consumer = tokio::spawn(async move {
+            let mut got = Vec::new();
+            while let Some(value) = rx.recv().await {
+                got.push(value);
+                if got.len() == 2 {
+                    break;
+                }
             }
+            got
         });
+        tx.send_all([1, 2]).await.unwrap();
+        assert_eq!(consumer.await.unwrap(), [1, 2]);
+
+        // The receiver is gone now; the rest stays with the caller
+        let mut items = [3, 4, 5].into_iter();
+        assert_eq!(tx.send_all(&mut items).await, Err(SendError::Closed(3)));
+        assert_eq!(items.collect::<Vec<_>>(), [4, 5]);
+    }
 
+    #[test]
+    fn test_waiting_lens() {
+        let (tx, rx) = channel::<u32>(2);
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(rx.waiting_receivers_len(), 1);
+        assert_eq!(recv.as_mut().poll(&mut cx), Poll::Pending); // keeps its place
+        assert_eq!(rx.waiting_receivers_len(), 1);
+        drop(recv);
+        assert_eq!(rx.waiting_receivers_len(), 0);
+
+        tx.try_send(1).unwrap();
+        let mut send = Box::pin(tx.send(2));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(tx.waiting_senders_len(), 1);
+        rx.try_recv().unwrap(); // dequeues the sender to wake it
+        assert_eq!(tx.waiting_senders_len(), 0);
+    }
+
+    #[test]
+    fn test_same_channel() {
+        let (tx1, rx1) = channel::<u32>(4);
+        let (tx2, rx2) = channel::<u32>(4);
+
+        assert!(tx1.same_channel(&tx1.clone()));
+        assert!(!tx1.same_channel(&tx2));
+        assert!(rx1.same_channel(&rx1.clone()));
+        assert!(!rx1.same_channel(&rx2));
+    }
+
+    #[test]
+    fn test_is_closed() {
+        let (tx, rx) = channel::<u32>(4);
+        assert!(!tx.is_closed() && !rx.is_closed());
+
+        tx.try_send(1).unwrap();
         drop(tx);
+        assert!(!rx.is_closed()); // still has a message to hand out
+        rx.try_recv().unwrap();
+        assert!(rx.is_closed());
 
-        set.spawn(async move {
-            while let Some(got) = rx.recv().await {
-                recv_clone.lock().unwrap().insert(got);
+        let (tx, rx) = channel::<u32>(4);
+        drop(rx);
+        assert!(tx.is_closed());
+    }
+
+    #[test]
+    fn test_channel_rounded() {
+        let (tx, rx) = channel_rounded::<u32>(5);
+        assert_eq!(tx.inner.buffer.capacity(), 8);
+        assert!(channel_rounded::<u32>(0).1.inner.rendezvous.is_none());
+        drop(rx);
+    }
+
+    #[test]
+    fn test_try_channel() {
+        assert_eq!(
+            try_channel::<u32>(6).err(),
+            Some(CapacityError::NotPowerOfTwo(6))
+        );
+        assert_eq!(try_channel::<u32>(1).err(), Some(CapacityError::SingleSlot));
+
+        let (tx, rx) = try_channel::<u32>(4).unwrap();
+        tx.try_send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+
+        // 0 still means rendezvous
+        assert!(try_channel::<u32>(0).unwrap().0.inner.rendezvous.is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 2")]
+    fn test_channel_rejects_single_slot() {
+        channel::<u32>(1);
+    }
+
+    #[test]
+    fn test_channel_named() {
+        let (tx, rx) = channel_named::<u32>(4, "ingest");
+
+        assert_eq!(tx.name(), Some("ingest"));
+        assert_eq!(rx.clone().name(), Some("ingest"));
+        assert!(format!("{tx:?}").contains(r#"name: Some("ingest")"#));
+        assert_eq!(channel::<u32>(4).0.name(), None);
+    }
+
+    #[test]
+    fn test_flush_waits_for_rendezvous_handoff() {
+        let (tx, rx) = channel::<u32>(0);
+        let (recv_woken, flush_woken) = (CountingWaker::new(), CountingWaker::new());
+
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(&mut recv, &recv_woken.waker()).is_pending());
+        tx.try_send(1).unwrap();
+
+        // The value sits in the slot until the receiver takes it
+        let mut flush = Box::pin(tx.flush());
+        assert!(poll_once(&mut flush, &flush_woken.waker()).is_pending());
+
+        assert_eq!(
+            poll_once(&mut recv, &recv_woken.waker()),
+            Poll::Ready(Some(1))
+        );
+        assert_eq!(flush_woken.count(), 1);
+        assert!(poll_once(&mut flush, &flush_woken.waker()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_flush_waits_for_drain() {
+        let (tx, rx) = channel::<u32>(8);
+        tx.flush().await;
+
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        let consumer = tokio::spawn(async move {
+            for _ in 0..3 {
+                tokio::task::yield_now().await;
+                rx.recv().await.unwrap();
             }
+            rx
         });
 
-        while let Some(result) = set.join_next().await {
-            result.unwrap();
+        tx.flush().await;
+        assert!(tx.inner.buffer.is_empty());
+
+        // A parked flush resolves once the last receiver is gone
+        let rx = consumer.await.unwrap();
+        tx.send(3).await.unwrap();
+        let flush = tokio::spawn(async move { tx.flush().await });
+        tokio::task::yield_now().await;
+        drop(rx);
+        flush.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_closed_resolves_when_last_sender_drops() {
+        let (tx, rx) = channel::<u32>(4);
+        tx.send(1).await.unwrap();
+        let tx2 = tx.clone();
+
+        let watcher = tokio::spawn(async move {
+            rx.closed().await;
+            rx
+        });
+        tokio::task::yield_now().await;
+
+        drop(tx);
+        tokio::task::yield_now().await;
+        assert!(!watcher.is_finished());
+
+        drop(tx2);
+        let rx = watcher.await.unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_last_drop_wakes_other_side() {
+        let (tx, rx) = channel::<u32>(2);
+        let parked_rx = tokio::spawn(async move { rx.recv().await });
+        tokio::task::yield_now().await;
+        drop(tx);
+        assert_eq!(parked_rx.await.unwrap(), None);
+
+        let (tx, rx) = channel::<u32>(2);
+        tx.send(1).await.unwrap();
+        let parked_tx = tokio::spawn(async move { tx.send(2).await });
+        tokio::task::yield_now().await;
+        drop(rx);
+        assert_eq!(parked_tx.await.unwrap(), Err(SendError::Closed(2)));
+
+        // Rendezvous: the value is handed over but never taken
+        let (tx, rx) = channel::<u32>(0);
+        let mut recv = Box::pin(rx.recv());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+
+        let parked_tx = tokio::spawn(async move { tx.send(3).await });
+        tokio::task::yield_now().await;
+        assert!(
+            rx.inner
+                .rendezvous
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .slot
+                .is_some()
+        );
+
+        drop(recv);
+        drop(rx);
+        assert_eq!(parked_tx.await.unwrap(), Err(SendError::Closed(3)));
+    }
+
+    #[tokio::test]
+    async fn test_peek_leaves_message_queued() {
+        let (tx, rx) = channel::<String>(2);
+        assert_eq!(rx.peek(), None);
+
+        tx.send(String::from("first")).await.unwrap();
+        let mut parked = Box::pin(tx.send(String::from("second")));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(parked.as_mut().poll(&mut cx).is_pending());
+
+        assert_eq!(rx.peek().as_deref(), Some("first"));
+        assert_eq!(rx.peek().as_deref(), Some("first"));
+        assert_eq!(rx.inner.buffer.len(), 1);
+        assert_eq!(rx.inner.waiting_senders.len(), 1);
+
+        assert_eq!(rx.recv().await.as_deref(), Some("first"));
+    }
+
+    #[tokio::test]
+    async fn test_high_water_mark_tracks_peak_len() {
+        let (tx, rx) = channel::<u32>(8);
+        assert_eq!(tx.high_water_mark(), 0);
+
+        tx.send_all_or_cancel([1, 2, 3]).await.unwrap();
+        rx.recv().await.unwrap();
+        tx.send(4).await.unwrap();
+        assert_eq!(rx.high_water_mark(), 3);
+
+        rx.recv().await.unwrap();
+        assert_eq!(tx.reset_high_water_mark(), 3);
+        assert_eq!(rx.high_water_mark(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_sends_receives_and_rejections() {
+        let (tx, rx) = channel::<u32>(2);
+
+        tx.send(1).await.unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        rx.recv().await.unwrap();
+
+        let expected = ChannelMetrics {
+            total_sent: 1,
+            total_received: 1,
+            total_full_rejections: 1,
+        };
+        assert_eq!(tx.metrics(), expected);
+        assert_eq!(rx.metrics(), expected);
+
+        let (tx, rx) = channel::<u32>(0);
+        let handle = tokio::spawn(async move { rx.recv().await });
+        tx.send(7).await.unwrap();
+        assert_eq!(handle.await.unwrap(), Some(7));
+        assert_eq!(tx.metrics().total_sent, 1);
+        assert_eq!(tx.metrics().total_received, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_size_hint_tracks_occupancy() {
+        let (tx, rx) = channel::<u32>(8);
+        let mut stream = rx.stream();
+        assert_eq!(stream.size_hint(), (0, None));
+
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
         }
+        assert_eq!(stream.size_hint(), (3, None));
 
-        for val in sent.lock().unwrap().iter() {
-            let guard = received.lock().unwrap();
-            prop_assert_eq!(val, guard.get(val).unwrap());
+        let next = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+        assert_eq!(next, Some(0));
+        assert_eq!(stream.size_hint(), (2, None));
+
+        drop(tx);
+        let mut rest = vec![];
+        while let Some(value) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            rest.push(value);
         }
-        prop_assert_eq!(sent.lock().unwrap().len(), num_messages);
+        assert_eq!(rest, [1, 2]);
+        assert_eq!(stream.size_hint(), (0, Some(0)));
     }
 
     #[tokio::test]
-    async fn test_drop_wakes_receivers() {
-        let (tx, rx) = channel::<u32>(4);
+    async fn test_merge_round_robins_until_all_closed() {
+        let (tx1, rx1) = channel::<u32>(4);
+        let (tx2, rx2) = channel::<u32>(4);
+        for i in [1, 2] {
+            tx1.send(i).await.unwrap();
+            tx2.send(i * 10).await.unwrap();
+        }
 
-        // Spawn 2 receivers that will wait
-        let rx1 = rx.clone();
-        let h1 = tokio::spawn(async move {
-            println!("Receiver 1 starting");
-            let result = rx1.recv().await;
-            println!("Receiver 1 got: {:?}", result);
-            result
+        let mut merged = merge(vec![rx1, rx2]);
+        let mut order = vec![];
+        for _ in 0..4 {
+            order.push(merged.recv().await.unwrap());
+        }
+        assert_eq!(order, [1, 10, 2, 20]);
+
+        drop(tx1);
+        let late = tokio::spawn(async move { tx2.send(30).await });
+        assert_eq!(merged.recv().await, Some(30));
+        late.await.unwrap().unwrap();
+        assert_eq!(merged.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_watermark_events_alternate() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let (tx, rx) = channel_with_watermarks::<u32>(8, 1, 4, move |event| {
+            seen.lock().unwrap().push(event);
         });
 
-        let rx2 = rx.clone();
-        let h2 = tokio::spawn(async move {
-            println!("Receiver 2 starting");
-            let result = rx2.recv().await;
-            println!("Receiver 2 got: {:?}", result);
-            result
+        for i in 0..6 {
+            tx.send(i).await.unwrap();
+        }
+        for _ in 0..5 {
+            rx.recv().await.unwrap();
+        }
+        // Crossing high again after the low fires again
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                WatermarkEvent::High { len: 4 },
+                WatermarkEvent::Low { len: 1 },
+                WatermarkEvent::High { len: 4 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recv_result_reports_disconnect() {
+        async fn sum(rx: &Receiver<u32, Open>) -> Result<u32, RecvError> {
+            Ok(rx.recv_result().await? + rx.recv_result().await?)
+        }
+
+        let (tx, rx) = channel::<u32>(4);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        assert_eq!(sum(&rx).await, Ok(3));
+
+        tx.send(3).await.unwrap();
+        drop(tx);
+        assert_eq!(sum(&rx).await, Err(RecvError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn test_send_accepts_not_unpin_payload() {
+        #[derive(Debug)]
+        struct Pinned {
+            id: u32,
+            _pin: std::marker::PhantomPinned,
+        }
+
+        let (tx, rx) = channel::<Pinned>(2);
+        let pinned = |id| Pinned {
+            id,
+            _pin: std::marker::PhantomPinned,
+        };
+
+        tx.send(pinned(1)).await.unwrap();
+        assert!(
+            tx.send_timeout(pinned(2), Duration::from_millis(10))
+                .await
+                .is_err()
+        );
+        assert_eq!(rx.recv().await.map(|p| p.id), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_dropping_primary_receiver_closes_channel() {
+        let (tx, rx) = channel::<u32>(2);
+        let secondary = rx.clone();
+        let primary = rx.into_primary();
+        tx.send(1).await.unwrap();
+
+        let parked_tx = tx.clone();
+        let parked = tokio::spawn(async move { parked_tx.send(2).await });
+        tokio::task::yield_now().await;
+        assert_eq!(primary.receiver_count(), 2);
+
+        drop(primary);
+        assert_eq!(parked.await.unwrap(), Err(SendError::Closed(2)));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+
+        assert_eq!(secondary.recv().await, Some(1));
+        assert_eq!(secondary.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_abort_discards_buffer_and_wakes_everyone() {
+        let message = Arc::new(());
+        let (tx, rx) = channel::<Arc<()>>(2);
+        tx.send(message.clone()).await.unwrap();
+
+        let parked_tx = tx.clone();
+        let parked_send = tokio::spawn(async move { parked_tx.send(Arc::new(())).await.is_err() });
+        tokio::task::yield_now().await;
+
+        tx.abort();
+        assert!(parked_send.await.unwrap());
+        assert_eq!(Arc::strong_count(&message), 1);
+        assert!(rx.recv().await.is_none());
+
+        let (tx, rx) = channel::<u32>(2);
+        let parked_recv = tokio::spawn(async move { rx.recv().await });
+        tokio::task::yield_now().await;
+
+        tx.abort();
+        assert_eq!(parked_recv.await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_abort_clears_before_waking_anyone() {
+        // Blocks the waking thread until a receiver on another thread has
+        // tried to take something, the earliest it could react to the abort
+        struct RecvOnWake {
+            go: std::sync::mpsc::Sender<()>,
+            done: Mutex<std::sync::mpsc::Receiver<()>>,
+        }
+
+        impl Wake for RecvOnWake {
+            fn wake(self: Arc<Self>) {
+                self.go.send(()).unwrap();
+                lock(&self.done).recv().unwrap();
+            }
+        }
+
+        let (tx, rx) = channel::<u32>(2);
+        tx.try_send(1).unwrap();
+
+        let (go_tx, go_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let receiver = thread::spawn(move || {
+            go_rx.recv().unwrap();
+            let got = rx.try_recv();
+            done_tx.send(()).unwrap();
+            got
         });
 
-        // Let receivers start waiting
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        // Park a sender on the full buffer so the abort wakes it
+        let waker = Waker::from(Arc::new(RecvOnWake {
+            go: go_tx,
+            done: Mutex::new(done_rx),
+        }));
+        let mut send = Box::pin(tx.send(2));
+        assert!(poll_once(&mut send, &waker).is_pending());
 
-        println!("Dropping all senders");
+        tx.abort();
+        assert_eq!(receiver.join().unwrap(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_send_racing_abort_leaves_nothing_to_receive() {
+        for _ in 0..200 {
+            let (tx, rx) = channel::<u32>(8);
+            let racer = tx.clone();
+            let start = std::sync::Barrier::new(2);
+
+            thread::scope(|s| {
+                s.spawn(|| {
+                    start.wait();
+                    let mut i = 0;
+                    while !matches!(racer.try_send(i), Err(TrySendError::Closed(_))) {
+                        i += 1;
+                    }
+                });
+
+                start.wait();
+                tx.abort();
+                assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+            });
+
+            // Whatever the racing sender pushed was dropped, not left queued
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+            assert!(rx.inner.buffer.is_empty());
+        }
+
+        // The losing interleaving, step by step: a sender past its closed
+        // check pushes only after the abort's clear
+        let (tx, rx) = channel::<u32>(8);
+        tx.abort();
+        tx.inner.buffer.push(1).unwrap();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        tx.inner.on_push(1);
+        assert!(rx.inner.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_abort_discards_rendezvous_handoff() {
+        let message = Arc::new(());
+        let (tx, rx) = channel::<Arc<()>>(0);
+        let (send_waker, recv_waker) = (CountingWaker::new(), CountingWaker::new());
+
+        // Park a receiver so the send deposits its value, then abort before
+        // the receiver gets to take it
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(&mut recv, &recv_waker.waker()).is_pending());
+        let mut send = Box::pin(tx.send(message.clone()));
+        assert!(poll_once(&mut send, &send_waker.waker()).is_pending());
+
+        tx.abort();
+        assert_eq!(Arc::strong_count(&message), 1);
+        assert_eq!(poll_once(&mut recv, &recv_waker.waker()), Poll::Ready(None));
+        assert_eq!(
+            poll_once(&mut send, &send_waker.waker()),
+            Poll::Ready(Ok(()))
+        );
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn test_close_rejects_sends_but_drains_buffer() {
+        let (tx, rx) = channel::<u32>(2);
+        tx.send(1).await.unwrap();
+
+        let parked_tx = tx.clone();
+        let parked = tokio::spawn(async move { parked_tx.send(2).await });
+        tokio::task::yield_now().await;
+
+        rx.close();
+        assert_eq!(parked.await.unwrap(), Err(SendError::Closed(2)));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_many_keeps_burst_contiguous() {
+        let (tx, rx) = channel::<u32>(8);
+        assert!(matches!(
+            tx.reserve_many(8).await,
+            Err(SendError::BufferFull(()))
+        ));
+
+        let mut permit = tx.reserve_many(4).await.unwrap();
+        permit.send(1);
+        tx.try_send(100).unwrap();
+        permit.send(2);
+        permit.send(3);
+        assert_eq!(permit.remaining(), 1);
+
+        // Of the 7 usable slots, 4 are reserved and 1 holds the interleaved send
+        assert_eq!(tx.inner.buffer.remaining(), 2);
+        drop(permit);
+        assert_eq!(tx.inner.buffer.remaining(), 3);
+
+        let mut out = Vec::new();
+        rx.recv_many(&mut out, 8).await;
+        assert_eq!(out, [100, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_channel_with_custom_buffer() {
+        // Holds up to 2 messages
+        #[derive(Debug, Default)]
+        struct DequeBuffer(Mutex<VecDeque<u32>>);
+
+        impl Buffer<u32> for DequeBuffer {
+            fn push(&self, value: u32) -> Result<(), u32> {
+                let mut deque = self.0.lock().unwrap();
+                if deque.len() == 2 {
+                    return Err(value);
+                }
+                deque.push_back(value);
+                Ok(())
+            }
+
+            fn pop(&self) -> Option<u32> {
+                self.0.lock().unwrap().pop_front()
+            }
+
+            fn push_overwrite<E: FnMut(u32)>(&self, value: u32, mut evicted: E) -> Result<(), u32> {
+                let mut deque = self.0.lock().unwrap();
+                if deque.len() == 2 {
+                    evicted(deque.pop_front().unwrap());
+                }
+                deque.push_back(value);
+                Ok(())
+            }
+
+            fn len(&self) -> usize {
+                self.0.lock().unwrap().len()
+            }
+
+            fn max_len(&self) -> usize {
+                2
+            }
+
+            fn is_full(&self) -> bool {
+                self.len() == 2
+            }
+        }
+
+        let (tx, rx) = channel_with_buffer(DequeBuffer::default());
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        let parked = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.send(3).await }
+        });
+        assert_eq!(rx.recv().await, Some(1));
+        parked.await.unwrap().unwrap();
         drop(tx);
 
-        // Both should complete with None
-        assert_eq!(h1.await.unwrap(), None);
-        assert_eq!(h2.await.unwrap(), None);
-        println!("Test passed!");
+        let mut out = Vec::new();
+        rx.recv_many(&mut out, 4).await;
+        assert_eq!(out, [2, 3]);
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_mapped_sender_wraps_values() {
+        let (tx, rx) = channel::<(u8, String)>(4);
+        let tx = tx.with(|body: &str| (1, body.to_string()));
+        let tx2 = tx.clone();
+        assert_eq!(rx.sender_count(), 2);
+
+        tx.send("a").await.unwrap();
+        tx2.try_send("b").unwrap();
+        drop((tx, tx2));
+
+        assert_eq!(rx.recv().await, Some((1, String::from("a"))));
+        assert_eq!(rx.recv().await, Some((1, String::from("b"))));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[test]
+    fn test_wake_all_spans_several_batches() {
+        let list = WaitList::default();
+        let woken = CountingWaker::new();
+        let waker = woken.waker();
+
+        let mut waiters: Vec<_> = (0..WAKE_BATCH * 2 + 3)
+            .map(|_| Waiter::new(&list))
+            .collect();
+        waiters
+            .iter_mut()
+            .for_each(|waiter| waiter.register(&waker));
+
+        list.wake(WAKE_BATCH + 1);
+        assert_eq!(woken.count(), WAKE_BATCH + 1);
+
+        list.wake_all();
+        assert_eq!(woken.count(), WAKE_BATCH * 2 + 3);
+        assert!(list.is_empty());
+        waiters.iter_mut().for_each(Waiter::deregister);
+    }
+
+    #[test]
+    fn test_wait_list_mirrors_queue_length() {
+        let list = WaitList::default();
+        let (mut a, mut b) = (Waiter::new(&list), Waiter::new(&list));
+
+        a.register(Waker::noop());
+        b.register(Waker::noop());
+        a.register(Waker::noop()); // still queued, keeps its place
+        assert_eq!(list.queued.load(Ordering::Relaxed), 2);
+
+        list.wake(1);
+        assert_eq!(list.queued.load(Ordering::Relaxed), 1);
+
+        // `a` was woken, so dropping it passes the wakeup on to `b`
+        drop(a);
+        assert_eq!(list.queued.load(Ordering::Relaxed), 0);
+        b.deregister();
+        assert!(list.is_empty());
     }
 }
@@ -0,0 +1,122 @@
+use super::ring_buffer::{RingBuffer, next_index, occupied};
+use core::mem::MaybeUninit;
+
+/// A ring buffer whose storage is an inline array of `N` slots, so it
+/// never touches the heap. `N` must be a power of two of at least 2,
+/// checked when the buffer is built: a bad `N` fails to compile instead of
+/// panicking.
+///
+/// Like `RingBuffer`, one slot always stays empty to tell full from empty,
+/// so it holds at most `N - 1` elements. There is no lock: it is meant to
+/// be owned by one thread, and every mutation takes `&mut self`.
+pub struct ConstRingBuffer<T, const N: usize> {
+    buffer: [MaybeUninit<T>; N],
+    head: usize, // next write position
+    tail: usize, // next read position
+}
+
+impl<T, const N: usize> ConstRingBuffer<T, N> {
+    // A single slot would always be full, as one slot always stays empty
+    const VALID_CAPACITY: () = assert!(
+        N >= 2 && N.is_power_of_two(),
+        "Capacity must be a power of 2 of at least 2"
+    );
+
+    pub const fn new() -> Self {
+        // Evaluated at compile time for every `N` this is instantiated with
+        let () = Self::VALID_CAPACITY;
+
+        Self {
+            buffer: [const { MaybeUninit::uninit() }; N],
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        occupied(self.head, self.tail, N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    pub fn is_full(&self) -> bool {
+        next_index(self.head, N) == self.tail
+    }
+
+    /// Appends `value`, handing it back if the buffer is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        // SAFETY: The buffer isn't full, so head is a free slot, and
+        // `&mut self` rules out any other access
+        unsafe { RingBuffer::write_slot(self.buffer.as_mut_ptr(), self.head, value) };
+        self.head = next_index(self.head, N);
+
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // SAFETY: The buffer isn't empty, so tail holds a value. Advancing
+        // tail past it below means it is never read again.
+        let value = unsafe { RingBuffer::read_slot(self.buffer.as_mut_ptr(), self.tail) };
+        self.tail = next_index(self.tail, N);
+
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Default for ConstRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for ConstRingBuffer<T, N> {
+    fn drop(&mut self) {
+        // SAFETY: Elements between tail and head are initialized, and the
+        // buffer is never used again
+        unsafe {
+            RingBuffer::<T>::drop_range(self.buffer.as_mut_ptr(), self.tail, self.head, N);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_wraps_and_drops_what_is_left() {
+        let counter = Rc::new(());
+        let mut rb = ConstRingBuffer::<Rc<()>, 4>::new();
+
+        for _ in 0..3 {
+            rb.push(counter.clone()).unwrap();
+        }
+        assert!(rb.push(counter.clone()).is_err());
+
+        // Move head and tail around the end of the array
+        for _ in 0..2 {
+            rb.pop().unwrap();
+            rb.push(counter.clone()).unwrap();
+        }
+        assert_eq!(rb.len(), 3);
+        assert_eq!(Rc::strong_count(&counter), 4);
+
+        drop(rb);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
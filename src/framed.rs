@@ -0,0 +1,99 @@
+use super::channel::{Open, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// Splits a byte channel into frames ending in `delimiter`, e.g. lines.
+/// A partial frame is flushed as-is once no byte has arrived for
+/// `flush_after`, or when the channel closes.
+pub struct FramedReceiver {
+    receiver: Receiver<u8, Open>,
+    delimiter: u8,
+    flush_after: Duration,
+    partial: Vec<u8>,
+}
+
+impl FramedReceiver {
+    pub fn new(receiver: Receiver<u8, Open>, delimiter: u8, flush_after: Duration) -> Self {
+        Self {
+            receiver,
+            delimiter,
+            flush_after,
+            partial: Vec::new(),
+        }
+    }
+
+    /// The next frame, without its delimiter. `None` once the channel is
+    /// closed and everything buffered has been yielded.
+    pub async fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            // Nothing to flush yet, so there is no reason to time out
+            let byte = if self.partial.is_empty() {
+                self.receiver
+                    .recv()
+                    .await
+                    .ok_or(RecvTimeoutError::Disconnected)
+            } else {
+                self.receiver.recv_timeout(self.flush_after).await
+            };
+
+            match byte {
+                Ok(byte) if byte != self.delimiter => self.partial.push(byte),
+                Err(RecvTimeoutError::Disconnected) if self.partial.is_empty() => return None,
+                _ => return Some(std::mem::take(&mut self.partial)),
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> Receiver<u8, Open> {
+        self.receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::channel;
+
+    #[tokio::test]
+    async fn test_splits_on_delimiter() {
+        let (tx, rx) = channel::<u8>(16);
+        let mut framed = FramedReceiver::new(rx, b'\n', Duration::from_secs(5));
+
+        for &byte in b"a\nbc\n" {
+            tx.send(byte).await.unwrap();
+        }
+
+        assert_eq!(framed.next_frame().await.as_deref(), Some(&b"a"[..]));
+        assert_eq!(framed.next_frame().await.as_deref(), Some(&b"bc"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_flushes_partial_frame_on_close() {
+        let (tx, rx) = channel::<u8>(16);
+        let mut framed = FramedReceiver::new(rx, b'\n', Duration::from_secs(5));
+
+        for &byte in b"a\nde" {
+            tx.send(byte).await.unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(framed.next_frame().await.as_deref(), Some(&b"a"[..]));
+        assert_eq!(framed.next_frame().await.as_deref(), Some(&b"de"[..]));
+        assert_eq!(framed.next_frame().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flushes_partial_frame_after_timeout() {
+        let (tx, rx) = channel::<u8>(16);
+        let mut framed = FramedReceiver::new(rx, b'\n', Duration::from_secs(5));
+
+        for &byte in b"ab" {
+            tx.send(byte).await.unwrap();
+        }
+
+        // `tx` stays alive, so only the timeout can flush the frame
+        let start = tokio::time::Instant::now();
+        assert_eq!(framed.next_frame().await.as_deref(), Some(&b"ab"[..]));
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+        drop(tx);
+    }
+}
@@ -0,0 +1,144 @@
+use super::{
+    channel::{WaitList, Waiter},
+    sync::lock,
+};
+use std::{
+    future::poll_fn,
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+
+#[derive(Debug)]
+struct LatestInner<T> {
+    value: Mutex<Option<T>>, // the newest value the receiver hasn't taken
+    waiting_receiver: WaitList,
+    sender_count: Arc<()>,
+}
+
+#[derive(Debug)]
+pub struct LatestSender<T> {
+    inner: Arc<LatestInner<T>>,
+    _sender_ref: ManuallyDrop<Arc<()>>, // clone of sender_count, released in `drop`
+}
+
+#[derive(Debug)]
+pub struct LatestReceiver<T> {
+    inner: Arc<LatestInner<T>>,
+}
+
+/// Creates a single-slot channel where the newest value wins: `set` never
+/// waits, it replaces whatever the receiver hasn't taken yet. Suits state
+/// like configuration, where only the current version matters.
+pub fn latest_channel<T>() -> (LatestSender<T>, LatestReceiver<T>) {
+    let sender_count = Arc::new(());
+
+    let inner = Arc::new(LatestInner {
+        value: Mutex::new(None),
+        waiting_receiver: WaitList::default(),
+        sender_count: sender_count.clone(),
+    });
+
+    let sender = LatestSender {
+        inner: inner.clone(),
+        _sender_ref: ManuallyDrop::new(sender_count),
+    };
+
+    (sender, LatestReceiver { inner })
+}
+
+impl<T> LatestInner<T> {
+    // One reference to the count token is held by `LatestInner` itself
+    fn sender_count(&self) -> usize {
+        Arc::strong_count(&self.sender_count) - 1
+    }
+}
+
+impl<T> LatestSender<T> {
+    /// Stores `value` for the receiver, returning the value it replaced if
+    /// the receiver hadn't taken that one yet.
+    pub fn set(&self, value: T) -> Option<T> {
+        let previous = lock(&self.inner.value).replace(value);
+        self.inner.waiting_receiver.wake(1);
+
+        previous
+    }
+}
+
+impl<T> LatestReceiver<T> {
+    /// Takes the newest value, waiting until one is set. `None` once every
+    /// sender is gone and the last value was taken.
+    pub async fn recv(&self) -> Option<T> {
+        let inner = &self.inner;
+        let take = || lock(&inner.value).take();
+
+        let mut waiter = Waiter::new(&inner.waiting_receiver);
+        let res = poll_fn(|cx| {
+            if let Some(value) = take() {
+                return Poll::Ready(Some(value));
+            }
+            if inner.sender_count() == 0 {
+                return Poll::Ready(take());
+            }
+
+            waiter.register(cx.waker());
+
+            // A value or the last sender's drop may have landed before we
+            // registered
+            if inner.sender_count() == 0 || lock(&inner.value).is_some() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+}
+
+impl<T> Clone for LatestSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _sender_ref: self._sender_ref.clone(),
+        }
+    }
+}
+
+impl<T> Drop for LatestSender<T> {
+    fn drop(&mut self) {
+        // Release our token before counting, as in `PrioritySender::drop`
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._sender_ref) };
+
+        if self.inner.sender_count() == 0 {
+            self.inner.waiting_receiver.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{CountingWaker, poll_once};
+
+    #[tokio::test]
+    async fn test_newest_value_wins() {
+        let (tx, rx) = latest_channel::<u32>();
+
+        let woken = CountingWaker::new();
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(&mut recv, &woken.waker()).is_pending());
+
+        assert_eq!(tx.set(1), None);
+        assert_eq!(tx.set(2), Some(1));
+        assert_eq!(woken.count(), 1);
+        assert_eq!(recv.await, Some(2));
+
+        tx.set(3);
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, None);
+    }
+}
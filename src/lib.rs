@@ -1,3 +1,29 @@
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod broadcast;
+pub mod buffer;
+#[cfg(feature = "std")]
 pub mod channel;
+#[cfg(feature = "std")]
 pub mod channel_repl;
+pub mod const_ring_buffer;
+#[cfg(feature = "std")]
+pub mod framed;
+#[cfg(feature = "std")]
+pub mod latest;
+#[cfg(feature = "std")]
+pub mod mpmc_ring_buffer;
+#[cfg(feature = "std")]
+pub mod oneshot;
+#[cfg(feature = "std")]
+pub mod priority;
 pub mod ring_buffer;
+mod sync;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod unbounded;
+pub mod weighted_ring_buffer;
@@ -0,0 +1,436 @@
+use super::buffer::Buffer;
+use std::mem::MaybeUninit;
+use sync::{AtomicUsize, Cell, Ordering};
+
+// Under `--cfg loom` the atomics and slot cells come from loom, so the
+// model checker can explore every interleaving of `try_push`/`try_pop`
+#[cfg(not(loom))]
+mod sync {
+    pub use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug)]
+    pub struct Cell<T>(std::cell::UnsafeCell<T>);
+
+    impl<T> Cell<T> {
+        pub fn new(value: T) -> Self {
+            Self(std::cell::UnsafeCell::new(value))
+        }
+
+        pub fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+            f(self.0.get())
+        }
+    }
+}
+
+#[cfg(loom)]
+mod sync {
+    pub use loom::cell::UnsafeCell as Cell;
+    pub use loom::sync::atomic::{AtomicUsize, Ordering};
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    // Equals the slot's push position while it is free, and that position
+    // plus one once it holds a value. Popping advances it a whole lap, so a
+    // stale position from an earlier lap can never match (no ABA).
+    sequence: AtomicUsize,
+    value: Cell<MaybeUninit<T>>,
+}
+
+/// A bounded multi-producer multi-consumer queue that coordinates through
+/// per-slot sequence numbers instead of a lock (Vyukov's bounded MPMC
+/// queue). Unlike `RingBuffer`, every slot is usable.
+#[derive(Debug)]
+pub struct MpmcRingBuffer<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    push_pos: AtomicUsize,
+    pop_pos: AtomicUsize,
+}
+
+// SAFETY: A slot's value is only touched by the one thread that claimed its
+// position through the CAS on `push_pos`/`pop_pos`, and the sequence number's
+// release/acquire pair publishes the value between them.
+unsafe impl<T: Send> Send for MpmcRingBuffer<T> {}
+unsafe impl<T: Send> Sync for MpmcRingBuffer<T> {}
+
+impl<T> MpmcRingBuffer<T> {
+    /// # Panics
+    /// If `capacity` is not a power of two of at least 2. With a single
+    /// slot, the sequence number meaning "free for the next lap" equals the
+    /// one meaning "full on this lap", so pushes would overwrite each other.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity >= 2 && capacity.is_power_of_two(),
+            "Capacity must be a power of 2 of at least 2"
+        );
+
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: Cell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Self {
+            slots,
+            mask: capacity - 1,
+            push_pos: AtomicUsize::new(0),
+            pop_pos: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Claimed positions, so it counts pushes and pops still in flight.
+    /// Only a snapshot while other threads are pushing or popping.
+    pub fn len(&self) -> usize {
+        let pop = self.pop_pos.load(Ordering::Relaxed);
+        let push = self.push_pos.load(Ordering::Relaxed);
+
+        // The two loads aren't one snapshot: pops that landed in between can
+        // put `pop` ahead of `push`, which means empty rather than a huge
+        // wrapped difference
+        match push.wrapping_sub(pop) as isize {
+            ..0 => 0,
+            len => (len as usize).min(self.capacity()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Pushes without waiting, handing `value` back if the queue is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.push_pos.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let lag = sequence.wrapping_sub(pos) as isize;
+
+            if lag == 0 {
+                // Slot is free for this lap; race other producers for it
+                match self.push_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: Winning the CAS gives us sole access to
+                        // the slot until we publish it below
+                        slot.value.with_mut(|ptr| unsafe { (*ptr).write(value) });
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if lag < 0 {
+                // Still holds the value from the previous lap
+                return Err(value);
+            } else {
+                // Another producer claimed `pos` first
+                pos = self.push_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops without waiting. `None` if the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        let mut pos = self.pop_pos.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let lag = sequence.wrapping_sub(pos.wrapping_add(1)) as isize;
+
+            if lag == 0 {
+                match self.pop_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The acquire load saw the producer's
+                        // release, so the slot is initialized and ours alone
+                        let value = slot
+                            .value
+                            .with_mut(|ptr| unsafe { (*ptr).assume_init_read() });
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if lag < 0 {
+                // Nothing pushed into this slot yet
+                return None;
+            } else {
+                pos = self.pop_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// Lets a channel run on lock-free storage via `channel_with_buffer`
+impl<T> Buffer<T> for MpmcRingBuffer<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        self.try_push(value)
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.try_pop()
+    }
+
+    // Evicting and pushing can't happen as one step here, so a racing push
+    // may take the freed slot and force another eviction
+    fn push_overwrite<E: FnMut(T)>(&self, mut value: T, mut evicted: E) -> Result<(), T> {
+        loop {
+            match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(rejected) => value = rejected,
+            }
+
+            if let Some(oldest) = self.try_pop() {
+                evicted(oldest);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        MpmcRingBuffer::len(self)
+    }
+
+    fn max_len(&self) -> usize {
+        self.capacity()
+    }
+
+    fn is_full(&self) -> bool {
+        MpmcRingBuffer::is_full(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        MpmcRingBuffer::is_empty(self)
+    }
+}
+
+impl<T> Drop for MpmcRingBuffer<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_every_slot_usable() {
+        let rb = MpmcRingBuffer::new(4);
+        for i in 0..4 {
+            rb.try_push(i).unwrap();
+        }
+        assert_eq!(rb.try_push(4), Err(4));
+
+        for i in 0..4 {
+            assert_eq!(rb.try_pop(), Some(i));
+        }
+        assert_eq!(rb.try_pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2")]
+    fn test_rejects_single_slot() {
+        MpmcRingBuffer::<u32>::new(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2")]
+    fn test_channel_rejects_single_slot_buffer() {
+        crate::channel::channel_with_buffer::<u32, _>(MpmcRingBuffer::new(1));
+    }
+
+    #[tokio::test]
+    async fn test_channel_over_lock_free_buffer() {
+        let (tx, rx) = crate::channel::channel_with_buffer(MpmcRingBuffer::new(2));
+
+        let producer = tokio::spawn(async move {
+            for i in 0..10 {
+                tx.send(i).await.unwrap();
+            }
+        });
+
+        for i in 0..10 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        producer.await.unwrap();
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[test]
+    fn test_concurrent_no_loss_or_duplication() {
+        const PRODUCERS: usize = 4;
+        const PER_PRODUCER: usize = 10_000;
+
+        let rb = Arc::new(MpmcRingBuffer::new(64));
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let rb = rb.clone();
+                thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let mut value = p * PER_PRODUCER + i;
+                        while let Err(rejected) = rb.try_push(value) {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..PRODUCERS)
+            .map(|_| {
+                let rb = rb.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::with_capacity(PER_PRODUCER);
+                    while seen.len() < PER_PRODUCER {
+                        match rb.try_pop() {
+                            Some(value) => seen.push(value),
+                            None => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut all: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        all.sort_unstable();
+
+        assert_eq!(all, (0..PRODUCERS * PER_PRODUCER).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent_push_overwrite_reports_every_eviction() {
+        const PUSHERS: usize = 4;
+        const PER_PUSHER: usize = 10_000;
+
+        let rb = Arc::new(MpmcRingBuffer::new(4));
+
+        let pushers: Vec<_> = (0..PUSHERS)
+            .map(|p| {
+                let rb = rb.clone();
+                thread::spawn(move || {
+                    let mut evicted = Vec::new();
+                    for i in 0..PER_PUSHER {
+                        rb.push_overwrite(p * PER_PUSHER + i, |oldest| evicted.push(oldest))
+                            .unwrap();
+                    }
+                    evicted
+                })
+            })
+            .collect();
+
+        // Whatever isn't left in the buffer must have been reported
+        let mut all: Vec<_> = pushers
+            .into_iter()
+            .flat_map(|pusher| pusher.join().unwrap())
+            .collect();
+        all.extend(std::iter::from_fn(|| rb.try_pop()));
+        all.sort_unstable();
+
+        assert_eq!(all, (0..PUSHERS * PER_PUSHER).collect::<Vec<_>>());
+    }
+}
+
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release loom`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn loom_push_pop_no_loss_or_duplication() {
+        loom::model(|| {
+            let rb = Arc::new(MpmcRingBuffer::new(2));
+
+            let producers: Vec<_> = (0..2)
+                .map(|i| {
+                    let rb = rb.clone();
+                    thread::spawn(move || rb.try_push(i).unwrap())
+                })
+                .collect();
+
+            let consumer = {
+                let rb = rb.clone();
+                thread::spawn(move || rb.try_pop())
+            };
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            let mut seen: Vec<_> = consumer.join().unwrap().into_iter().collect();
+            seen.extend(rb.try_pop());
+            seen.extend(rb.try_pop());
+            seen.sort_unstable();
+
+            assert_eq!(seen, [0, 1]);
+            assert_eq!(rb.try_pop(), None);
+        });
+    }
+
+    #[test]
+    fn loom_two_producers_two_consumers() {
+        // Exhausting every interleaving of four threads takes hours; bugs
+        // in this kind of queue surface within a few preemptions
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+
+        builder.check(|| {
+            let rb = Arc::new(MpmcRingBuffer::new(2));
+
+            let producers: Vec<_> = (0..2)
+                .map(|i| {
+                    let rb = rb.clone();
+                    thread::spawn(move || rb.try_push(i).unwrap())
+                })
+                .collect();
+
+            // The main thread is the second consumer
+            let consumer = {
+                let rb = rb.clone();
+                thread::spawn(move || rb.try_pop())
+            };
+            let mut seen: Vec<_> = rb.try_pop().into_iter().collect();
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            seen.extend(consumer.join().unwrap());
+            while let Some(value) = rb.try_pop() {
+                seen.push(value);
+            }
+            seen.sort_unstable();
+
+            assert_eq!(seen, [0, 1]);
+            assert!(rb.is_empty());
+        });
+    }
+}
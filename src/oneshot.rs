@@ -0,0 +1,147 @@
+use super::sync::lock;
+use std::{
+    fmt::Display,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use thiserror::Error;
+
+/// The sender was dropped without sending.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub struct RecvError;
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sender dropped without sending")
+    }
+}
+
+#[derive(Debug)]
+struct OneshotState<T> {
+    value: Option<T>,
+    waker: Option<Waker>, // the receiver's, while it waits
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+#[derive(Debug)]
+pub struct OneshotSender<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+/// Resolves to the sent value when awaited.
+#[derive(Debug)]
+pub struct OneshotReceiver<T> {
+    state: Arc<Mutex<OneshotState<T>>>,
+}
+
+/// Creates a single-use channel carrying exactly one value, e.g. the reply
+/// to a request.
+pub fn channel<T>() -> (OneshotSender<T>, OneshotReceiver<T>) {
+    let state = Arc::new(Mutex::new(OneshotState {
+        value: None,
+        waker: None,
+        sender_alive: true,
+        receiver_alive: true,
+    }));
+
+    let sender = OneshotSender {
+        state: state.clone(),
+    };
+
+    (sender, OneshotReceiver { state })
+}
+
+impl<T> OneshotSender<T> {
+    /// Hands `value` to the receiver, or back to the caller if the receiver
+    /// is already gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = lock(&self.state);
+
+        if !state.receiver_alive {
+            return Err(value);
+        }
+
+        state.value = Some(value);
+        let waker = state.waker.take();
+        drop(state);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for OneshotSender<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.sender_alive = false;
+        let waker = state.waker.take();
+        drop(state);
+
+        // Lets a waiting receiver see that nothing is coming
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for OneshotReceiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+
+        if !state.sender_alive {
+            return Poll::Ready(Err(RecvError));
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for OneshotReceiver<T> {
+    fn drop(&mut self) {
+        lock(&self.state).receiver_alive = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_wakes_receiver() {
+        let (tx, rx) = channel::<String>();
+
+        let handle = tokio::spawn(rx);
+        tokio::task::yield_now().await;
+
+        tx.send(String::from("reply")).unwrap();
+        assert_eq!(handle.await.unwrap().as_deref(), Ok("reply"));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_sender_errors() {
+        let (tx, rx) = channel::<u32>();
+        drop(tx);
+
+        assert_eq!(rx.await, Err(RecvError));
+    }
+
+    #[test]
+    fn test_send_fails_without_receiver() {
+        let (tx, rx) = channel::<u32>();
+        drop(rx);
+
+        assert_eq!(tx.send(1), Err(1));
+    }
+}
@@ -0,0 +1,312 @@
+use super::{
+    channel::{SendError, WaitList, Waiter},
+    ring_buffer::RingBuffer,
+    sync::lock,
+};
+use std::{
+    future::poll_fn,
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+
+#[derive(Debug)]
+struct PriorityInner<T> {
+    high: RingBuffer<T>,
+    normal: RingBuffer<T>,
+    waiting_high: WaitList,   // senders parked on a full `high`
+    waiting_normal: WaitList, // senders parked on a full `normal`
+    waiting_receivers: WaitList,
+    high_streak: Mutex<usize>, // high messages received since the last normal one
+    fairness: Option<usize>,
+    sender_count: Arc<()>,
+    receiver_count: Arc<()>,
+}
+
+#[derive(Debug)]
+pub struct PrioritySender<T> {
+    inner: Arc<PriorityInner<T>>,
+    _sender_ref: ManuallyDrop<Arc<()>>, // clone of sender_count, released in `drop`
+}
+
+#[derive(Debug)]
+pub struct PriorityReceiver<T> {
+    inner: Arc<PriorityInner<T>>,
+    _receiver_ref: ManuallyDrop<Arc<()>>, // clone of receiver_count, released in `drop`
+}
+
+/// Creates a channel with two lanes of `capacity` each: messages sent with
+/// `send_priority` are always received before those sent with `send`.
+///
+/// A steady stream of priority messages therefore starves normal ones
+/// indefinitely. Use `priority_channel_with_fairness` to bound that.
+pub fn priority_channel<T>(capacity: usize) -> (PrioritySender<T>, PriorityReceiver<T>) {
+    build_priority_channel(capacity, None)
+}
+
+/// Like `priority_channel`, but after `ratio` priority messages in a row a
+/// waiting normal message goes next, so normal traffic always progresses.
+pub fn priority_channel_with_fairness<T>(
+    capacity: usize,
+    ratio: usize,
+) -> (PrioritySender<T>, PriorityReceiver<T>) {
+    assert!(ratio > 0, "Fairness ratio must be at least 1");
+    build_priority_channel(capacity, Some(ratio))
+}
+
+fn build_priority_channel<T>(
+    capacity: usize,
+    fairness: Option<usize>,
+) -> (PrioritySender<T>, PriorityReceiver<T>) {
+    let sender_count = Arc::new(());
+    let receiver_count = Arc::new(());
+
+    let inner = Arc::new(PriorityInner {
+        high: RingBuffer::new(capacity),
+        normal: RingBuffer::new(capacity),
+        waiting_high: WaitList::default(),
+        waiting_normal: WaitList::default(),
+        waiting_receivers: WaitList::default(),
+        high_streak: Mutex::new(0),
+        fairness,
+        sender_count: sender_count.clone(),
+        receiver_count: receiver_count.clone(),
+    });
+
+    let sender = PrioritySender {
+        inner: inner.clone(),
+        _sender_ref: ManuallyDrop::new(sender_count),
+    };
+
+    let receiver = PriorityReceiver {
+        inner,
+        _receiver_ref: ManuallyDrop::new(receiver_count),
+    };
+
+    (sender, receiver)
+}
+
+impl<T> PriorityInner<T> {
+    // One reference to each count token is held by `PriorityInner` itself
+    fn sender_count(&self) -> usize {
+        Arc::strong_count(&self.sender_count) - 1
+    }
+
+    fn receiver_count(&self) -> usize {
+        Arc::strong_count(&self.receiver_count) - 1
+    }
+
+    /// Pops from the high lane first, unless fairness says a normal
+    /// message is due.
+    fn pop(&self) -> Option<T> {
+        let mut streak = lock(&self.high_streak);
+        let normal_due = self.fairness.is_some_and(|ratio| *streak >= ratio);
+
+        let high_first = if normal_due {
+            self.normal.pop().map(|value| (value, false))
+        } else {
+            self.high.pop().map(|value| (value, true))
+        };
+        let (value, was_high) = high_first
+            .or_else(|| self.high.pop().map(|value| (value, true)))
+            .or_else(|| self.normal.pop().map(|value| (value, false)))?;
+
+        if was_high {
+            *streak += 1;
+            drop(streak);
+            self.waiting_high.wake(1);
+        } else {
+            *streak = 0;
+            drop(streak);
+            self.waiting_normal.wake(1);
+        }
+
+        Some(value)
+    }
+
+    async fn send(
+        &self,
+        lane: &RingBuffer<T>,
+        waiting: &WaitList,
+        value: T,
+    ) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        let mut waiter = Waiter::new(waiting);
+
+        let res = poll_fn(|cx| {
+            let item = value.take().expect("polled after completion");
+
+            if self.receiver_count() == 0 {
+                return Poll::Ready(Err(SendError::Closed(item)));
+            }
+
+            match lane.push(item) {
+                Ok(()) => {
+                    self.waiting_receivers.wake(1);
+                    Poll::Ready(Ok(()))
+                }
+                Err(rejected) => {
+                    value = Some(rejected);
+                    waiter.register(cx.waker());
+
+                    // A receiver may have popped, or the last one dropped,
+                    // between our failed push and registering
+                    if !lane.is_full() || self.receiver_count() == 0 {
+                        cx.waker().wake_by_ref();
+                    }
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+}
+
+impl<T> PrioritySender<T> {
+    /// Sends a normal message, waiting while the normal lane is full.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let inner = &self.inner;
+        inner
+            .send(&inner.normal, &inner.waiting_normal, value)
+            .await
+    }
+
+    /// Sends a message that jumps ahead of every normal one, waiting while
+    /// the priority lane is full. A full normal lane doesn't hold it up.
+    pub async fn send_priority(&self, value: T) -> Result<(), SendError<T>> {
+        let inner = &self.inner;
+        inner.send(&inner.high, &inner.waiting_high, value).await
+    }
+}
+
+impl<T> PriorityReceiver<T> {
+    /// The next message, priority lane first. `None` once every sender is
+    /// gone and both lanes are drained.
+    pub async fn recv(&self) -> Option<T> {
+        let inner = &self.inner;
+        let drained =
+            || inner.sender_count() == 0 && inner.high.is_empty() && inner.normal.is_empty();
+
+        let mut waiter = Waiter::new(&inner.waiting_receivers);
+        let res = poll_fn(|cx| {
+            if let Some(value) = inner.pop() {
+                return Poll::Ready(Some(value));
+            }
+            if drained() {
+                return Poll::Ready(None);
+            }
+
+            waiter.register(cx.waker());
+
+            if drained() || !inner.high.is_empty() || !inner.normal.is_empty() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+}
+
+impl<T> Clone for PrioritySender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _sender_ref: self._sender_ref.clone(),
+        }
+    }
+}
+
+impl<T> Clone for PriorityReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _receiver_ref: self._receiver_ref.clone(),
+        }
+    }
+}
+
+impl<T> Drop for PrioritySender<T> {
+    fn drop(&mut self) {
+        // Release our token before counting, so two senders dropping at once
+        // can't each see the other still alive and both skip the wakeup
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._sender_ref) };
+
+        if self.inner.sender_count() == 0 {
+            self.inner.waiting_receivers.wake_all();
+        }
+    }
+}
+
+impl<T> Drop for PriorityReceiver<T> {
+    fn drop(&mut self) {
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._receiver_ref) };
+
+        if self.inner.receiver_count() == 0 {
+            self.inner.waiting_high.wake_all();
+            self.inner.waiting_normal.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_priority_messages_jump_ahead() {
+        let (tx, rx) = priority_channel::<u32>(4);
+
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        tx.send_priority(10).await.unwrap();
+        drop(tx);
+
+        assert_eq!(rx.recv().await, Some(10));
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_fairness_lets_normal_through() {
+        let (tx, rx) = priority_channel_with_fairness::<u32>(8, 2);
+
+        for i in 0..2 {
+            tx.send(i).await.unwrap();
+        }
+        for i in 10..15 {
+            tx.send_priority(i).await.unwrap();
+        }
+        drop(tx);
+
+        let mut order = Vec::new();
+        while let Some(value) = rx.recv().await {
+            order.push(value);
+        }
+        assert_eq!(order, [10, 11, 0, 12, 13, 1, 14]);
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_is_per_lane() {
+        let (tx, rx) = priority_channel::<u32>(2);
+        tx.send(1).await.unwrap();
+
+        // The normal lane is full, but priority sends still get through
+        let parked_tx = tx.clone();
+        let parked = tokio::spawn(async move { parked_tx.send(2).await });
+        tx.send_priority(10).await.unwrap();
+
+        assert_eq!(rx.recv().await, Some(10));
+        assert_eq!(rx.recv().await, Some(1));
+        parked.await.unwrap().unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+    }
+}
@@ -1,23 +1,225 @@
-use std::mem::MaybeUninit;
-use std::sync::{
-    Mutex,
-    atomic::{AtomicUsize, Ordering},
-};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering, fence};
+use thiserror::Error;
 
-#[derive(Debug)]
+use super::sync::{Mutex, MutexGuard, lock};
+
+/// The slot after `index` in a ring of `capacity` slots.
+pub(crate) const fn next_index(index: usize, capacity: usize) -> usize {
+    (index + 1) % capacity
+}
+
+/// How many slots are taken between `tail` (next read) and `head` (next
+/// write), given one slot is always left free.
+pub(crate) const fn occupied(head: usize, tail: usize, capacity: usize) -> usize {
+    (head + capacity - tail) % capacity
+}
+
+/// A bounded FIFO queue over a power-of-two array of slots.
+///
+/// Every operation takes `lock`, `push` and `pop` included: the same lock
+/// is what makes `resize`, `retain`, `drain`, reservations and the other
+/// whole-buffer operations safe to run next to them. `MpmcRingBuffer`'s
+/// push and pop take no lock, at the cost of those operations; a channel
+/// can run on it through `channel_with_buffer`.
 pub struct RingBuffer<T> {
-    buffer: Vec<MaybeUninit<T>>,
-    capacity: usize,
-    head: AtomicUsize, // next write position
-    tail: AtomicUsize, // next read position
+    buffer: UnsafeCell<Vec<MaybeUninit<T>>>, // swapped out by `resize` under `lock`
+    capacity: AtomicUsize,
+    head: AtomicUsize,     // next write position
+    tail: AtomicUsize,     // next read position
+    reserved: AtomicUsize, // free slots promised to `push_reserved`
+    resizes: AtomicUsize,  // odd while `resize` is moving the indices
     lock: Mutex<()>,
+    #[cfg(debug_assertions)]
+    initialized: UnsafeCell<Vec<u64>>, // one bit per slot, set while the slot holds a value
+}
+
+/// Returned by `RingBuffer::debug_state`. `head` is the next slot to
+/// write and `tail` the next to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingBufferState {
+    pub head: usize,
+    pub tail: usize,
+    pub capacity: usize,
+    pub len: usize,
+    pub is_full: bool,
+    pub is_empty: bool,
+}
+
+/// Borrows the oldest element in place, returned by `RingBuffer::front`.
+/// Dropping it drops the element and frees its slot. The buffer stays
+/// locked while the guard lives, so pushes wait on it too.
+pub struct FrontGuard<'a, T> {
+    buffer: &'a RingBuffer<T>,
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl<T> Deref for FrontGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: `front` only makes a guard for a non-empty buffer, and
+        // the lock we hold keeps anyone else from popping
+        unsafe { self.buffer.live_ref(0) }
+    }
+}
+
+impl<T> Drop for FrontGuard<'_, T> {
+    fn drop(&mut self) {
+        // Still locked: fields drop after this
+        let buffer = self.buffer;
+        let tail = buffer.tail.load(Ordering::Relaxed);
+
+        #[cfg(debug_assertions)]
+        buffer.track_slot(tail, false);
+
+        // Free the slot first, so a panicking `T::drop` can't leave it to be
+        // dropped again
+        buffer
+            .tail
+            .store(next_index(tail, buffer.capacity()), Ordering::Relaxed);
+
+        // SAFETY: tail held the element we lent out, and the lock keeps the
+        // freed slot from being reused until we return. Dropping in place
+        // spares a large `T` the move a `pop` would make.
+        unsafe { (*buffer.base_ptr().add(tail)).assume_init_drop() };
+    }
+}
+
+/// Yields up to a fixed number of the oldest elements, returned by
+/// `RingBuffer::drain`. Dropping it early still removes and drops the ones
+/// it didn't yield. The buffer stays locked while it lives.
+pub struct Drain<'a, T> {
+    buffer: &'a RingBuffer<T>,
+    remaining: usize, // never more than the buffer's length
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        self.buffer.pop_locked()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        // Still locked: fields drop after this
+        let buffer = self.buffer;
+        let capacity = buffer.capacity();
+        let tail = buffer.tail.load(Ordering::Relaxed);
+        let end = (tail + self.remaining) % capacity;
+
+        #[cfg(debug_assertions)]
+        for offset in 0..self.remaining {
+            buffer.track_slot((tail + offset) % capacity, false);
+        }
+
+        // As in `FrontGuard`, free the slots before dropping what's in them
+        buffer.tail.store(end, Ordering::Relaxed);
+
+        // SAFETY: `remaining` never exceeds the length, so `tail..end` holds
+        // initialized elements, which nothing reaches any more
+        unsafe { RingBuffer::drop_range(buffer.base_ptr(), tail, end, capacity) };
+    }
+}
+
+/// A capacity `RingBuffer` can't use, as returned by `try_new`.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CapacityError {
+    Zero,
+    /// One slot always stays empty, so a single slot could hold nothing.
+    SingleSlot,
+    NotPowerOfTwo(usize),
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero => write!(f, "Capacity must be non-zero"),
+            Self::SingleSlot => write!(f, "Capacity must be at least 2"),
+            Self::NotPowerOfTwo(capacity) => {
+                write!(f, "Capacity must be power of 2, got {capacity}")
+            }
+        }
+    }
+}
+
+/// The power-of-two capacity needed to hold exactly `len` elements, given
+/// that one slot always stays empty to tell full from empty. Never less
+/// than 2, the smallest capacity `RingBuffer::new` accepts.
+///
+/// # Panics
+/// If that capacity doesn't fit in a `usize`.
+pub fn capacity_for(len: usize) -> usize {
+    len.checked_add(1)
+        .and_then(usize::checked_next_power_of_two)
+        .unwrap_or_else(|| panic!("No power-of-two capacity can hold {len} elements"))
+        .max(2)
 }
 
+// SAFETY: The storage is only touched while holding `lock` or through
+// `&mut self`, so sharing the buffer only ever moves `T`s between threads.
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
 impl<T> RingBuffer<T> {
+    /// Capacity used by `RingBuffer::default()`.
+    pub const DEFAULT_CAPACITY: usize = 16;
+
+    /// # Panics
+    /// If `capacity` is less than 2 or not a power of two; see `try_new`.
     pub fn new(capacity: usize) -> Self {
-        assert!(capacity.is_power_of_two(), "Capacity must be power of 2");
+        match Self::try_new(capacity) {
+            Ok(buffer) => buffer,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Like `new`, but reports an unusable `capacity` instead of panicking,
+    /// for capacities that come from user input.
+    pub fn try_new(capacity: usize) -> Result<Self, CapacityError> {
+        if capacity == 0 {
+            return Err(CapacityError::Zero);
+        }
+        if capacity == 1 {
+            return Err(CapacityError::SingleSlot);
+        }
+        if !capacity.is_power_of_two() {
+            return Err(CapacityError::NotPowerOfTwo(capacity));
+        }
+
+        Ok(Self::with_storage(Vec::with_capacity(capacity), capacity))
+    }
+
+    /// A buffer that is always full and never holds anything, for
+    /// rendezvous channels, which must never buffer a value.
+    #[cfg(feature = "std")]
+    pub(crate) fn single_slot() -> Self {
+        Self::with_storage(Vec::with_capacity(1), 1)
+    }
 
-        let mut buffer = Vec::with_capacity(capacity);
+    /// Builds an empty buffer on `buffer`, which must have room for
+    /// `capacity` slots.
+    fn with_storage(mut buffer: Vec<MaybeUninit<T>>, capacity: usize) -> Self {
+        debug_assert!(buffer.capacity() >= capacity);
 
         // SAFETY: As the vector's internals are uninitialized,
         // we need to explicitly state it's length that corresponds
@@ -27,136 +229,1785 @@ impl<T> RingBuffer<T> {
         }
 
         Self {
-            buffer,
-            capacity,
+            buffer: UnsafeCell::new(buffer),
+            capacity: AtomicUsize::new(capacity),
             head: AtomicUsize::new(0),
             tail: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+            resizes: AtomicUsize::new(0),
             lock: Mutex::new(()),
+            #[cfg(debug_assertions)]
+            initialized: UnsafeCell::new(alloc::vec![0; capacity.div_ceil(64)]),
+        }
+    }
+
+    /// A buffer with `min_capacity` rounded up to a power of two (and to at
+    /// least 2, so it can hold an element). Like `new`, one slot stays
+    /// empty; `capacity()` reports what was actually allocated.
+    pub fn new_rounded(min_capacity: usize) -> Self {
+        Self::new(min_capacity.max(2).next_power_of_two())
+    }
+
+    /// A buffer just large enough to hold `len` elements.
+    ///
+    /// # Panics
+    /// If `len` is too large for any power-of-two capacity; see
+    /// `capacity_for`.
+    pub fn sized_for(len: usize) -> Self {
+        Self::new(capacity_for(len))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    // Callers must hold `lock` (or `&mut self`) for as long as they use the
+    // pointer, since `resize` may swap the storage out from under it.
+    fn base_ptr(&self) -> *mut MaybeUninit<T> {
+        unsafe { (*self.buffer.get()).as_mut_ptr() }
+    }
+
+    /// The element at logical `index` (0 = next to pop).
+    ///
+    /// # Safety
+    /// Callers must hold `lock` for as long as they use the reference, and
+    /// `index` must be less than `len()`.
+    unsafe fn live_ref(&self, index: usize) -> &T {
+        let slot = (self.tail.load(Ordering::Relaxed) + index) % self.capacity();
+        unsafe { (*self.base_ptr().add(slot)).assume_init_ref() }
+    }
+
+    /// Flips the tracking bit for `index`, asserting the slot was in the
+    /// opposite state. Catches index regressions that would double-drop or
+    /// read uninitialized memory. Callers must hold `lock` or `&mut self`.
+    #[cfg(debug_assertions)]
+    fn track_slot(&self, index: usize, init: bool) {
+        let bit = 1u64 << (index % 64);
+        let word = unsafe { &mut (&mut *self.initialized.get())[index / 64] };
+        let prev = *word;
+        *word ^= bit;
+
+        if init {
+            assert!(prev & bit == 0, "slot {index} written twice without a read");
+        } else {
+            assert!(prev & bit != 0, "slot {index} read while uninitialized");
+        }
+    }
+
+    /// Rebuilds the tracking bitset from `tail..head` after elements were
+    /// moved between slots wholesale. Callers must hold `lock` or `&mut self`.
+    #[cfg(debug_assertions)]
+    fn retrack_slots(&self) {
+        let capacity = self.capacity();
+        let head = self.head.load(Ordering::Relaxed);
+        let mut current = self.tail.load(Ordering::Relaxed);
+        let initialized = unsafe { &mut *self.initialized.get() };
+
+        *initialized = alloc::vec![0; capacity.div_ceil(64)];
+        while current != head {
+            initialized[current / 64] |= 1 << (current % 64);
+            current = next_index(current, capacity);
+        }
+    }
+
+    /// Drops the elements in slots `tail..head`, oldest first, wrapping at
+    /// `capacity`. Like dropping a slice, it carries on past a panicking
+    /// destructor and re-raises the panic once the rest are dropped.
+    ///
+    /// # Safety
+    /// Those slots must be initialized, and callers must already treat them
+    /// as free so nothing reads or drops them again.
+    pub(crate) unsafe fn drop_range(
+        base: *mut MaybeUninit<T>,
+        tail: usize,
+        head: usize,
+        capacity: usize,
+    ) {
+        struct DropOnExit<T>(*mut [T]);
+
+        impl<T> Drop for DropOnExit<T> {
+            fn drop(&mut self) {
+                // SAFETY: Forwarded from `drop_range`
+                unsafe { core::ptr::drop_in_place(self.0) };
+            }
         }
+
+        let base = base.cast::<T>();
+        let (front_len, back_len) = if head >= tail {
+            (head - tail, 0)
+        } else {
+            (capacity - tail, head)
+        };
+
+        // The wrapped-around part still gets dropped if the front panics
+        let _back = DropOnExit(core::ptr::slice_from_raw_parts_mut(base, back_len));
+        let front = core::ptr::slice_from_raw_parts_mut(unsafe { base.add(tail) }, front_len);
+        unsafe { core::ptr::drop_in_place(front) };
+    }
+
+    /// Moves `value` into slot `index`.
+    ///
+    /// # Safety
+    /// `index` must be in bounds of `base`'s storage and the slot free, and
+    /// callers must have exclusive access to it.
+    pub(crate) unsafe fn write_slot(base: *mut MaybeUninit<T>, index: usize, value: T) {
+        unsafe { (*base.add(index)).write(value) };
+    }
+
+    /// Moves the value out of slot `index`, leaving it free.
+    ///
+    /// # Safety
+    /// As for `write_slot`, except the slot must be initialized, and callers
+    /// must treat it as free afterwards.
+    pub(crate) unsafe fn read_slot(base: *mut MaybeUninit<T>, index: usize) -> T {
+        unsafe { (*base.add(index)).assume_init_read() }
     }
 
     /// Try to push a value into the buffer.
     /// Returns Err(value) if buffer is full.
     pub fn push(&self, value: T) -> Result<(), T> {
-        let _guard = self.lock.lock().unwrap();
+        let _guard = lock(&self.lock);
+        self.push_locked(value)
+    }
 
+    // Body of `push`; callers must hold `lock`
+    fn push_locked(&self, value: T) -> Result<(), T> {
         let head = self.head.load(Ordering::Relaxed);
-        let next_head = (head + 1) % self.capacity;
 
         if self.is_full() {
             return Err(value);
         }
 
-        let base = self.buffer.as_ptr() as *mut MaybeUninit<T>;
-        let slot_ptr = unsafe { base.add(head) };
+        #[cfg(debug_assertions)]
+        self.track_slot(head, true);
 
         // SAFETY: Lock ensures no concurrent access. head verified
-        // to point to uninitialized slot (not full). Transferring
-        // ownership of `value` into the slot via ptr::write.
-        unsafe {
-            (*slot_ptr).write(value);
+        // to point to uninitialized slot (not full).
+        unsafe { Self::write_slot(self.base_ptr(), head, value) };
+
+        self.head
+            .store(next_index(head, self.capacity()), Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Pushes from `iter` until it runs out or the buffer fills, returning
+    /// how many were pushed and, if the buffer filled first, the rest of the
+    /// iterator. Never pulls an item it has no room for.
+    pub fn push_iter<I: IntoIterator<Item = T>>(&self, iter: I) -> (usize, Option<I::IntoIter>) {
+        let _guard = lock(&self.lock);
+        let mut iter = iter.into_iter();
+        let mut count = 0;
+
+        loop {
+            if self.is_full() {
+                return (count, Some(iter));
+            }
+
+            let Some(value) = iter.next() else {
+                return (count, None);
+            };
+            if self.push_locked(value).is_err() {
+                unreachable!("checked for room above");
+            }
+            count += 1;
+        }
+    }
+
+    /// Like `push_iter`, but collects whatever didn't fit into a `Vec`,
+    /// in order. The lock is taken once for the whole batch.
+    pub fn try_push_batch<I: IntoIterator<Item = T>>(&self, items: I) -> Vec<T> {
+        let (_, rest) = self.push_iter(items);
+        rest.map_or_else(Vec::new, Iterator::collect)
+    }
+
+    /// Like `push`, but builds the element with `f` directly in its slot.
+    /// `f` is only called if there is room.
+    #[allow(clippy::result_unit_err)]
+    pub fn push_with<F: FnOnce() -> T>(&self, f: F) -> Result<(), ()> {
+        // SAFETY: `write` initializes the whole slot
+        unsafe { self.push_with_ptr(|slot| slot.write(f())) }
+    }
+
+    /// Hands `f` a pointer to the next free slot to initialize in place, so
+    /// `T` never has to be moved. `f` is only called if there is room.
+    ///
+    /// # Safety
+    /// `f` must fully initialize the `T` behind the pointer before it
+    /// returns. If it panics instead, the slot stays free and whatever it
+    /// wrote is leaked.
+    #[allow(clippy::result_unit_err)]
+    pub unsafe fn push_with_ptr<F: FnOnce(*mut T)>(&self, f: F) -> Result<(), ()> {
+        let _guard = lock(&self.lock);
+        let head = self.head.load(Ordering::Relaxed);
+
+        if self.is_full() {
+            return Err(());
         }
 
-        self.head.store(next_head, Ordering::Relaxed);
+        // SAFETY: Lock held, and head points at a free slot since the buffer
+        // isn't full. The caller guarantees `f` initializes it.
+        unsafe { f((*self.base_ptr().add(head)).as_mut_ptr()) };
+
+        #[cfg(debug_assertions)]
+        self.track_slot(head, true);
+
+        self.head
+            .store(next_index(head, self.capacity()), Ordering::Relaxed);
 
         Ok(())
     }
 
     pub fn pop(&self) -> Option<T> {
-        let _guard = self.lock.lock().unwrap();
+        let _guard = lock(&self.lock);
+        self.pop_locked()
+    }
+
+    // Body of `pop`; callers must hold `lock`
+    fn pop_locked(&self) -> Option<T> {
         let tail = self.tail.load(Ordering::Relaxed);
 
         if self.is_empty() {
             return None;
         }
 
-        let base = self.buffer.as_ptr();
+        #[cfg(debug_assertions)]
+        self.track_slot(tail, false);
 
         // SAFETY: Lock ensures exclusive access. is_empty() check
         // guarantees tail points to initialized data. Reading moves
         // the value out, leaving slot uninitialized (OK because tail
         // will advance past it).
-        let slot_ptr = unsafe { base.add(tail) };
-        let value = unsafe { MaybeUninit::assume_init_read(&*slot_ptr) };
+        let value = unsafe { Self::read_slot(self.base_ptr(), tail) };
 
-        let next_tail = (tail + 1) % self.capacity;
-        self.tail.store(next_tail, Ordering::Relaxed);
+        self.tail
+            .store(next_index(tail, self.capacity()), Ordering::Relaxed);
 
         Some(value)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.head.load(Ordering::Relaxed) == self.tail.load(Ordering::Relaxed)
+    /// Pops up to `limit` elements into `out` under one lock acquisition,
+    /// returning how many were moved.
+    pub fn pop_many(&self, out: &mut Vec<T>, limit: usize) -> usize {
+        let _guard = lock(&self.lock);
+        let mut count = 0;
+
+        while count < limit
+            && let Some(value) = self.pop_locked()
+        {
+            out.push(value);
+            count += 1;
+        }
+
+        count
     }
 
-    pub fn is_full(&self) -> bool {
-        (self.head.load(Ordering::Relaxed) + 1) % self.capacity == self.tail.load(Ordering::Relaxed)
+    /// Pushes all of `items` under one lock acquisition, or none of them if
+    /// they don't all fit. Returns the items back on failure.
+    pub fn push_all(&self, items: Vec<T>) -> Result<(), Vec<T>> {
+        let _guard = lock(&self.lock);
+
+        if items.len() > self.remaining() {
+            return Err(items);
+        }
+
+        for item in items {
+            // Cannot fail: room for the whole batch was checked above
+            let _ = self.push_locked(item);
+        }
+
+        Ok(())
     }
-}
 
-impl<T> Drop for RingBuffer<T> {
-    fn drop(&mut self) {
-        let mut current = self.tail.load(Ordering::Relaxed);
+    /// Pushes `value`, evicting and returning the oldest element first if
+    /// the buffer is full. If every free slot is reserved and there is
+    /// nothing to evict, `value` itself is returned.
+    pub fn push_overwrite(&self, value: T) -> Option<T> {
+        self.try_push_overwrite(value).unwrap_or_else(Some)
+    }
+
+    /// Like `push_overwrite`, but tells an evicted element (`Ok`) apart
+    /// from `value` being handed back because every free slot is reserved
+    /// and there was nothing to evict (`Err`).
+    pub fn try_push_overwrite(&self, value: T) -> Result<Option<T>, T> {
+        let _guard = lock(&self.lock);
+        let evicted = if self.is_full() {
+            self.pop_locked()
+        } else {
+            None
+        };
+
+        // Fails only if the room is all reserved and nothing was evicted
+        self.push_locked(value).map(|()| evicted)
+    }
+
+    /// Cache-flavoured name for `push_overwrite`: inserts `value`, evicting
+    /// the oldest element to make room if needed and returning it.
+    pub fn force_push(&self, value: T) -> Option<T> {
+        self.push_overwrite(value)
+    }
+
+    /// Cache-flavoured name for `pop`: evicts the oldest element.
+    pub fn pop_evict(&self) -> Option<T> {
+        self.pop()
+    }
+
+    /// Sets aside one free slot for a later `push_reserved`, so other pushes
+    /// see the buffer as that much fuller. Returns false if there is no room.
+    pub fn try_reserve(&self) -> bool {
+        self.try_reserve_many(1)
+    }
+
+    /// Sets aside `count` free slots at once, or none if fewer are free.
+    pub fn try_reserve_many(&self, count: usize) -> bool {
+        let _guard = lock(&self.lock);
+
+        if self.remaining() < count {
+            return false;
+        }
+
+        self.reserved.fetch_add(count, Ordering::Relaxed);
+        true
+    }
+
+    /// Pushes into a slot set aside by `try_reserve`; cannot fail.
+    pub fn push_reserved(&self, value: T) {
+        let _guard = lock(&self.lock);
+        self.take_reservations(1);
+
+        // Cannot fail: the reservation kept this slot free
+        let _ = self.push_locked(value);
+    }
+
+    /// Pushes `items` back to back into slots set aside by
+    /// `try_reserve_many`, one reservation each; cannot fail.
+    pub fn push_all_reserved(&self, items: Vec<T>) {
+        let _guard = lock(&self.lock);
+        self.take_reservations(items.len());
+
+        for value in items {
+            // Cannot fail: the reservations kept these slots free
+            let _ = self.push_locked(value);
+        }
+    }
+
+    /// Gives back a slot set aside by `try_reserve` without using it.
+    pub fn release_reservation(&self) {
+        self.release_reservations(1);
+    }
+
+    /// Gives back `count` reserved slots without using them.
+    pub fn release_reservations(&self, count: usize) {
+        let _guard = lock(&self.lock);
+        self.take_reservations(count);
+    }
+
+    // Callers must hold `lock`
+    fn take_reservations(&self, count: usize) {
+        let reserved = self.reserved.load(Ordering::Relaxed);
+        assert!(reserved >= count, "no reserved slot to take");
+        self.reserved.store(reserved - count, Ordering::Relaxed);
+    }
+
+    /// Applies `f` to every live element in FIFO order, under the lock.
+    pub fn map_in_place<F: FnMut(&mut T)>(&self, mut f: F) {
+        let _guard = lock(&self.lock);
+        let capacity = self.capacity();
         let head = self.head.load(Ordering::Relaxed);
+        let mut current = self.tail.load(Ordering::Relaxed);
+        let base = self.base_ptr();
 
         while current != head {
-            unsafe {
-                // SAFETY: Elements between tail and head are initialized.
-                // This loop walks through exactly those elements.
-                let ptr = self.buffer.as_ptr().add(current) as *mut MaybeUninit<T>;
-                (*ptr).assume_init_drop();
+            // SAFETY: Lock held, and slots between tail and head are initialized
+            unsafe { f((*base.add(current)).assume_init_mut()) };
+            current = next_index(current, capacity);
+        }
+    }
+
+    /// Drops every element, leaving the buffer empty. A panicking destructor
+    /// doesn't stop the others from being dropped.
+    pub fn clear(&self) {
+        let _guard = lock(&self.lock);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+
+        // Empty first, so a panic below leaves nothing to drop twice
+        self.tail.store(head, Ordering::Relaxed);
+        #[cfg(debug_assertions)]
+        self.retrack_slots();
+
+        // SAFETY: Lock held, `tail..head` was initialized, and those slots
+        // are now free
+        unsafe { Self::drop_range(self.base_ptr(), tail, head, self.capacity()) };
+    }
+
+    /// Drops every element for which `f` returns false, running its
+    /// destructor, and closes the gaps so the survivors keep their order in
+    /// one run starting at `tail`.
+    pub fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+        self.retain_or(f, drop);
+    }
+
+    /// Removes and returns, in order, every element for which `f` returns
+    /// true. The rest stay queued in their original order.
+    pub fn extract_if<F: FnMut(&T) -> bool>(&self, mut f: F) -> Vec<T> {
+        let mut extracted = Vec::new();
+        self.retain_or(|value| !f(value), |value| extracted.push(value));
+        extracted
+    }
+
+    // `retain`, handing each rejected element to `rejected` instead of
+    // necessarily dropping it
+    fn retain_or<F: FnMut(&T) -> bool, R: FnMut(T)>(&self, mut f: F, mut rejected: R) {
+        // Commits the compaction even if `f` or a destructor panics, so no
+        // slot is left dropped-but-live or live-but-forgotten
+        struct Compactor<'a, T> {
+            buffer: &'a RingBuffer<T>,
+            capacity: usize,
+            head: usize,
+            read: usize,  // next slot to inspect
+            write: usize, // next slot to hand a survivor
+        }
+
+        impl<T> Drop for Compactor<'_, T> {
+            fn drop(&mut self) {
+                let base = self.buffer.base_ptr();
+
+                // SAFETY: Lock held by `retain`. Anything `f` never got to
+                // is still initialized and slides down behind the survivors
+                while self.read != self.head {
+                    if self.read != self.write {
+                        unsafe { base.add(self.write).write(base.add(self.read).read()) };
+                    }
+                    self.read = next_index(self.read, self.capacity);
+                    self.write = next_index(self.write, self.capacity);
+                }
+
+                self.buffer.head.store(self.write, Ordering::Relaxed);
+
+                #[cfg(debug_assertions)]
+                self.buffer.retrack_slots();
+            }
+        }
+
+        let _guard = lock(&self.lock);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let mut compactor = Compactor {
+            buffer: self,
+            capacity: self.capacity(),
+            head: self.head.load(Ordering::Relaxed),
+            read: tail,
+            write: tail,
+        };
+        let base = self.base_ptr();
+
+        while compactor.read != compactor.head {
+            let read = compactor.read;
+            // SAFETY: Lock held, and slots between `read` and head are
+            // still initialized
+            let keep = f(unsafe { (*base.add(read)).assume_init_ref() });
+            compactor.read = next_index(read, compactor.capacity);
+
+            if keep {
+                if read != compactor.write {
+                    // SAFETY: `write` trails `read`, so its slot was already
+                    // moved out of or dropped
+                    unsafe { base.add(compactor.write).write(base.add(read).read()) };
+                }
+                compactor.write = next_index(compactor.write, compactor.capacity);
+            } else {
+                // SAFETY: The slot is initialized and, with `read` already
+                // past it, nothing will touch it again
+                rejected(unsafe { (*base.add(read)).assume_init_read() });
             }
-            current = (current + 1) % self.capacity;
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Runs `f` on the element at logical `index` (0 = next to pop), under
+    /// the lock. `None` if `index` is past the end.
+    pub fn with_slot<R, F: FnOnce(&mut T) -> R>(&self, index: usize, f: F) -> Option<R> {
+        let _guard = lock(&self.lock);
 
-    #[test]
-    fn push_pop_single() {
-        let rb = RingBuffer::new(4);
-        rb.push(42).unwrap();
-        assert_eq!(rb.pop(), Some(42));
+        if index >= self.len() {
+            return None;
+        }
+
+        let slot = (self.tail.load(Ordering::Relaxed) + index) % self.capacity();
+        // SAFETY: Lock held, and the slot lies between tail and head
+        Some(unsafe { f((*self.base_ptr().add(slot)).assume_init_mut()) })
     }
 
-    #[test]
-    fn test_pop_till_empty() {
-        let rb = RingBuffer::new(2);
-        rb.push(2).unwrap();
+    /// A clone of the next element to pop, leaving it in place.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let _guard = lock(&self.lock);
 
-        assert_eq!(rb.pop(), Some(2));
-        assert!(rb.is_empty());
-        assert!(rb.pop().is_none());
+        if self.is_empty() {
+            return None;
+        }
+
+        // SAFETY: Lock held, and the buffer is non-empty
+        Some(unsafe { self.live_ref(0) }.clone())
     }
 
-    #[test]
-    fn test_push_till_full() {
-        let rb = RingBuffer::new(2);
-        rb.push(2).unwrap();
+    /// Borrows the oldest element without moving it out. It is removed when
+    /// the guard drops; until then every other operation on the buffer
+    /// waits, so keep the guard short-lived.
+    pub fn front(&self) -> Option<FrontGuard<'_, T>> {
+        let guard = lock(&self.lock);
 
-        assert!(rb.push(2).is_err());
-        assert!(rb.is_full());
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(FrontGuard {
+            buffer: self,
+            _lock: guard,
+        })
     }
 
-    #[test]
-    fn test_wrap_around_strings() {
-        let rb = RingBuffer::new(4);
-        rb.push(String::from("hi")).unwrap();
-        rb.push(String::from("test")).unwrap();
-        rb.push(String::from("String")).unwrap();
+    /// Removes the `count` oldest elements (or all of them, if fewer),
+    /// yielding them in order. Whatever the iterator hasn't yielded when it
+    /// drops is dropped along with it, so exactly that many always leave
+    /// the buffer.
+    pub fn drain(&self, count: usize) -> Drain<'_, T> {
+        let guard = lock(&self.lock);
 
-        let _ = rb.pop();
+        Drain {
+            buffer: self,
+            remaining: count.min(self.len()),
+            _lock: guard,
+        }
+    }
 
-        rb.push(String::from("Wrapped")).unwrap();
+    /// Moves the element at logical `index` (0 = next to pop) to the front,
+    /// shifting the ones before it back by one. O(index) moves under the lock.
+    ///
+    /// # Panics
+    /// If `index` is not less than `len()`.
+    pub fn promote(&self, index: usize) {
+        let _guard = lock(&self.lock);
+        assert!(index < self.len(), "promote index {index} out of bounds");
+
+        let capacity = self.capacity();
+        let tail = self.tail.load(Ordering::Relaxed);
+        let base = self.base_ptr();
 
-        let head = rb.head.load(Ordering::Relaxed);
-        let tail = rb.tail.load(Ordering::Relaxed);
+        for offset in (0..index).rev() {
+            let a = (tail + offset) % capacity;
+            let b = (tail + offset + 1) % capacity;
+            // SAFETY: Lock held, and both slots lie between tail and head
+            unsafe { core::ptr::swap(base.add(a), base.add(b)) };
+        }
+    }
 
-        assert_eq!(head, 0);
-        assert_eq!(tail, 1);
-        assert!(rb.is_full());
+    /// `(capacity, head, tail)` for readers that don't take the lock.
+    /// `resize` changes all three, and pairing its new capacity with an
+    /// old index would wrap the arithmetic around, so this retries until
+    /// it reads them between resizes, seqlock style.
+    fn indices(&self) -> (usize, usize, usize) {
+        loop {
+            let resizes = self.resizes.load(Ordering::Acquire);
+            if resizes % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+
+            let capacity = self.capacity.load(Ordering::Relaxed);
+            let head = self.head.load(Ordering::Relaxed);
+            let tail = self.tail.load(Ordering::Relaxed);
+
+            fence(Ordering::Acquire);
+            if self.resizes.load(Ordering::Relaxed) == resizes {
+                return (capacity, head, tail);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let (capacity, head, tail) = self.indices();
+        occupied(head, tail, capacity)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let (_, head, tail) = self.indices();
+        head == tail
+    }
+
+    /// Reserved slots count as occupied.
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// How many more elements fit, not counting reserved slots.
+    pub fn remaining(&self) -> usize {
+        let used = self.len() + self.reserved.load(Ordering::Relaxed);
+        (self.capacity() - 1).saturating_sub(used)
+    }
+
+    /// A consistent snapshot of the internal indices, for asserting on
+    /// wrap-around behaviour from outside the module.
+    pub fn debug_state(&self) -> RingBufferState {
+        let _guard = lock(&self.lock);
+
+        RingBufferState {
+            head: self.head.load(Ordering::Relaxed),
+            tail: self.tail.load(Ordering::Relaxed),
+            capacity: self.capacity(),
+            len: self.len(),
+            is_full: self.is_full(),
+            is_empty: self.is_empty(),
+        }
+    }
+
+    /// `(tail, head)` read without taking the lock, for monitoring threads
+    /// that only want an occupancy estimate.
+    ///
+    /// The two loads are separate, so a push or pop running concurrently
+    /// can leave them from different moments, and a resize can pair indices
+    /// from two different capacities. Fine for approximate metrics, never
+    /// for deciding which slots hold values. Use `debug_state` for a
+    /// consistent view.
+    pub fn snapshot_indices(&self) -> (usize, usize) {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+
+        (tail, head)
+    }
+
+    /// Whether the live elements sit in one unwrapped run of slots.
+    /// A `head` of 0 means the run ends exactly at the buffer's end.
+    pub fn is_contiguous(&self) -> bool {
+        let _guard = lock(&self.lock);
+        let head = self.head.load(Ordering::Relaxed);
+
+        head == 0 || head >= self.tail.load(Ordering::Relaxed)
+    }
+
+    /// Lines the live elements up in one run of slots and returns them as
+    /// one slice in FIFO order. Like `VecDeque::make_contiguous`, it moves
+    /// as few elements as it can: none if they don't wrap, otherwise the
+    /// shorter run where there is room for it.
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        let capacity = *self.capacity.get_mut();
+        let len = occupied(head, tail, capacity);
+        let slots = self.buffer.get_mut().as_mut_ptr();
+
+        // A `head` of 0 means the run ends exactly at the buffer's end
+        let start = if head >= tail || head == 0 {
+            tail
+        } else {
+            // The live elements are `front` at tail..capacity followed by
+            // `back` at 0..head, with `free` empty slots between them
+            let front = capacity - tail;
+            let back = head;
+            let free = capacity - len;
+
+            // SAFETY: Every copy stays within the storage and moves the
+            // live slots into a single run, leaving each element in exactly
+            // one slot; `&mut self` guarantees no one else can touch them.
+            unsafe {
+                if free >= front {
+                    // DEFGH....ABC -> ABCDEFGH....
+                    core::ptr::copy(slots, slots.add(front), back);
+                    core::ptr::copy_nonoverlapping(slots.add(tail), slots, front);
+                    0
+                } else if free >= back {
+                    // FGH....ABCDE -> ...ABCDEFGH.
+                    core::ptr::copy(slots.add(tail), slots.add(back), front);
+                    core::ptr::copy_nonoverlapping(slots, slots.add(len), back);
+                    back
+                } else if front > back {
+                    // No room to move either run whole: slide the shorter
+                    // one up against the other, then rotate just the live run
+                    core::ptr::copy(slots, slots.add(free), back);
+                    self.buffer.get_mut()[free..].rotate_left(back);
+                    free
+                } else {
+                    core::ptr::copy(slots.add(tail), slots.add(back), front);
+                    self.buffer.get_mut()[..len].rotate_right(front);
+                    0
+                }
+            }
+        };
+
+        *self.tail.get_mut() = start;
+        *self.head.get_mut() = (start + len) % capacity;
+
+        #[cfg(debug_assertions)]
+        self.retrack_slots();
+
+        // SAFETY: Slots start..start + len now hold exactly the initialized
+        // elements, and `&mut self` guarantees no one else can touch them.
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.buffer.get_mut().as_mut_ptr().add(start) as *mut T,
+                len,
+            )
+        }
+    }
+
+    /// The live elements as two slices in FIFO order, split where they wrap
+    /// past the end of the storage. The second is empty when they don't.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        let slots = self.buffer.get_mut().as_mut_slice();
+
+        let (front, back) = if head >= tail {
+            (&mut slots[tail..head], &mut [][..])
+        } else {
+            let (wrapped, rest) = slots.split_at_mut(tail);
+            (rest, &mut wrapped[..head])
+        };
+
+        // SAFETY: Both ranges lie within `tail..head` (modulo wrapping), so
+        // every slot in them is initialized, and `&mut self` guarantees no
+        // one else can touch them. `MaybeUninit<T>` has `T`'s layout.
+        unsafe {
+            (
+                &mut *(front as *mut [MaybeUninit<T>] as *mut [T]),
+                &mut *(back as *mut [MaybeUninit<T>] as *mut [T]),
+            )
+        }
+    }
+
+    /// Enlarges the storage to `new_capacity` slots, keeping every queued
+    /// element in order. Unlike `resize`, can only ever add room.
+    ///
+    /// # Panics
+    /// If `new_capacity` is not a power of two larger than the current one.
+    pub fn grow(&mut self, new_capacity: usize) {
+        assert!(
+            new_capacity > self.capacity(),
+            "Capacity {new_capacity} does not exceed the current {}",
+            self.capacity()
+        );
+
+        self.resize(new_capacity);
+    }
+
+    /// Releases storage down to the smallest power-of-two capacity that is
+    /// at least `min_capacity` and still holds every queued element and
+    /// reservation. Never goes below 2 slots, since a single slot could
+    /// hold nothing. Does nothing if the buffer is already that small, or
+    /// if no power of two is at least `min_capacity`.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let Some(min_capacity) = min_capacity.checked_next_power_of_two() else {
+            return;
+        };
+        let used = self.len() + *self.reserved.get_mut();
+        let target = capacity_for(used).max(min_capacity);
+
+        if target < self.capacity() {
+            self.resize(target);
+        }
+    }
+
+    /// Releases as much storage as the queued elements allow.
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(1);
+    }
+
+    /// Reallocates to `new_capacity` slots, moving the live elements to the
+    /// front of the new storage in FIFO order.
+    ///
+    /// # Panics
+    /// If `new_capacity` is less than 2 or not a power of two, or too small
+    /// for the queued elements and reservations.
+    pub fn resize(&self, new_capacity: usize) {
+        assert!(new_capacity >= 2, "{}", CapacityError::SingleSlot);
+        assert!(
+            new_capacity.is_power_of_two(),
+            "Capacity must be power of 2"
+        );
+
+        let _guard = lock(&self.lock);
+        let capacity = self.capacity();
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let len = occupied(head, tail, capacity);
+
+        let reserved = self.reserved.load(Ordering::Relaxed);
+        assert!(
+            len + reserved < new_capacity,
+            "Capacity {new_capacity} cannot hold {len} elements and {reserved} reserved slots"
+        );
+
+        let mut buffer = Vec::with_capacity(new_capacity);
+
+        // SAFETY: Same as in `new`, the slots start out uninitialized
+        unsafe {
+            buffer.set_len(new_capacity);
+        }
+
+        // SAFETY: Lock held. Each live slot is moved bitwise exactly once
+        // into the new storage; the old Vec of `MaybeUninit` is then freed
+        // without running any destructors.
+        unsafe {
+            let base = self.base_ptr();
+            for (i, slot) in buffer.iter_mut().enumerate().take(len) {
+                *slot = base.add((tail + i) % capacity).read();
+            }
+            *self.buffer.get() = buffer;
+        }
+
+        self.resizes.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        self.capacity.store(new_capacity, Ordering::Relaxed);
+        self.tail.store(0, Ordering::Relaxed);
+        self.head.store(len, Ordering::Relaxed);
+
+        self.resizes.fetch_add(1, Ordering::Release);
+
+        #[cfg(debug_assertions)]
+        self.retrack_slots();
+    }
+}
+
+impl<T> Default for RingBuffer<T> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let _guard = lock(&self.lock);
+
+        // SAFETY: Lock held, and only the slots between tail and head,
+        // which are initialized, are read
+        let elements: Vec<&T> = (0..self.len())
+            .map(|i| unsafe { self.live_ref(i) })
+            .collect();
+
+        f.debug_struct("RingBuffer")
+            .field("capacity", &self.capacity())
+            .field("len", &elements.len())
+            .field("head", &self.head.load(Ordering::Relaxed))
+            .field("tail", &self.tail.load(Ordering::Relaxed))
+            .field("elements", &elements)
+            .finish()
+    }
+}
+
+/// Compares the live elements in FIFO order, ignoring where in the storage
+/// each buffer happens to keep them.
+impl<T: PartialEq> PartialEq for RingBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if core::ptr::eq(self, other) {
+            return true;
+        }
+
+        // Lock in address order so `a == b` and `b == a` racing can't deadlock
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let _first = lock(&first.lock);
+        let _second = lock(&second.lock);
+
+        // SAFETY: Both locks held, and indices stay below both lengths
+        self.len() == other.len()
+            && (0..self.len()).all(|i| unsafe { self.live_ref(i) == other.live_ref(i) })
+    }
+}
+
+impl<T: Eq> Eq for RingBuffer<T> {}
+
+/// Serializes the capacity and the live elements in FIFO order.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for RingBuffer<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let _guard = lock(&self.lock);
+
+        // SAFETY: Lock held, and only initialized slots are read
+        let elements: Vec<&T> = (0..self.len())
+            .map(|i| unsafe { self.live_ref(i) })
+            .collect();
+
+        let mut state = serializer.serialize_struct("RingBuffer", 2)?;
+        state.serialize_field("capacity", &self.capacity())?;
+        state.serialize_field("elements", &elements)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for RingBuffer<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "RingBuffer")]
+        struct Repr<T> {
+            capacity: usize,
+            elements: Vec<T>,
+        }
+
+        use serde::de::{Error, Unexpected};
+
+        let Repr { capacity, elements } = Repr::deserialize(deserializer)?;
+
+        // The input is untrusted: the capacity may be too small for the
+        // elements, or too large to round up or to allocate
+        let invalid = |expected: &str| {
+            D::Error::invalid_value(Unexpected::Unsigned(capacity as u64), &expected)
+        };
+        if capacity <= elements.len() {
+            return Err(invalid("a capacity with a free slot beyond the elements"));
+        }
+        let rounded = capacity
+            .max(2)
+            .checked_next_power_of_two()
+            .ok_or_else(|| invalid("a capacity that rounds up to a power of 2"))?;
+
+        let mut storage = Vec::new();
+        storage
+            .try_reserve_exact(rounded)
+            .map_err(|_| invalid("a capacity that can be allocated"))?;
+        let rb = Self::with_storage(storage, rounded);
+
+        // Cannot fail: the capacity was chosen to fit every element
+        let _ = rb.push_all(elements);
+
+        Ok(rb)
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+        let capacity = *self.capacity.get_mut();
+
+        // Catches an index regression that would drop a slot twice. Skipped
+        // while unwinding, where a second panic would abort.
+        #[cfg(debug_assertions)]
+        if !unwinding() {
+            for offset in 0..occupied(head, tail, capacity) {
+                self.track_slot((tail + offset) % capacity, false);
+            }
+        }
+
+        // SAFETY: Elements between tail and head are initialized, and the
+        // buffer is never used again
+        unsafe { Self::drop_range(self.buffer.get_mut().as_mut_ptr(), tail, head, capacity) };
+    }
+}
+
+#[cfg(all(debug_assertions, any(test, feature = "std")))]
+fn unwinding() -> bool {
+    std::thread::panicking()
+}
+
+// Without `std` there is no way to tell, and a panicking destructor aborts
+// anyway
+#[cfg(all(debug_assertions, not(any(test, feature = "std"))))]
+fn unwinding() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_pop_single() {
+        let rb = RingBuffer::new(4);
+        rb.push(42).unwrap();
+        assert_eq!(rb.pop(), Some(42));
+    }
+
+    #[test]
+    fn test_pop_till_empty() {
+        let rb = RingBuffer::new(2);
+        rb.push(2).unwrap();
+
+        assert_eq!(rb.pop(), Some(2));
+        assert!(rb.is_empty());
+        assert!(rb.pop().is_none());
+    }
+
+    #[test]
+    fn test_push_till_full() {
+        let rb = RingBuffer::new(2);
+        rb.push(2).unwrap();
+
+        assert!(rb.push(2).is_err());
+        assert!(rb.is_full());
+    }
+
+    // Runs against the spin lock too under `--no-default-features`
+    #[test]
+    fn test_concurrent_pushers_and_popper() {
+        let rb = RingBuffer::new(8);
+        let per_thread = 1000;
+
+        let popped = std::thread::scope(|s| {
+            for t in 0..3 {
+                let rb = &rb;
+                s.spawn(move || {
+                    for i in 0..per_thread {
+                        let mut value = t * per_thread + i;
+                        while let Err(rejected) = rb.push(value) {
+                            value = rejected;
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+
+            let mut popped = Vec::new();
+            while popped.len() < 3 * per_thread {
+                match rb.pop() {
+                    Some(value) => popped.push(value),
+                    None => std::thread::yield_now(),
+                }
+            }
+            popped
+        });
+
+        // Each pusher's values come out in the order it pushed them
+        for t in 0..3 {
+            let mine: Vec<_> = popped.iter().filter(|&&v| v / per_thread == t).collect();
+            assert!(mine.is_sorted());
+            assert_eq!(mine.len(), per_thread);
+        }
+        assert!(rb.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_indices_track_push_and_pop() {
+        let rb = RingBuffer::new(4);
+        assert_eq!(rb.snapshot_indices(), (0, 0));
+
+        for i in 0..3 {
+            rb.push(i).unwrap();
+        }
+        rb.pop();
+        assert_eq!(rb.snapshot_indices(), (1, 3));
+
+        rb.push(3).unwrap();
+        assert_eq!(rb.snapshot_indices(), (1, 0));
+    }
+
+    #[test]
+    fn test_wrap_around_strings() {
+        let rb = RingBuffer::new(4);
+        rb.push(String::from("hi")).unwrap();
+        rb.push(String::from("test")).unwrap();
+        rb.push(String::from("String")).unwrap();
+
+        let _ = rb.pop();
+
+        rb.push(String::from("Wrapped")).unwrap();
+
+        assert_eq!(
+            rb.debug_state(),
+            RingBufferState {
+                head: 0,
+                tail: 1,
+                capacity: 4,
+                len: 3,
+                is_full: true,
+                is_empty: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_push_overwrite_evicts_oldest() {
+        let rb = RingBuffer::new(4);
+        for i in 1..=3 {
+            assert_eq!(rb.push_overwrite(i), None);
+        }
+
+        assert_eq!(rb.push_overwrite(4), Some(1));
+        assert_eq!(rb.pop(), Some(2));
+        assert_eq!(rb.pop(), Some(3));
+        assert_eq!(rb.pop(), Some(4));
+    }
+
+    #[test]
+    fn test_try_push_overwrite_rejects_when_room_is_reserved() {
+        let rb = RingBuffer::new(2);
+        assert!(rb.try_reserve());
+        assert_eq!(rb.try_push_overwrite(1), Err(1));
+
+        rb.push_reserved(0);
+        assert_eq!(rb.try_push_overwrite(1), Ok(Some(0)));
+        assert_eq!(rb.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_dropping_drain_early_drops_the_rest() {
+        let counter = std::rc::Rc::new(());
+        let rb = RingBuffer::new(16);
+        for i in 0..8 {
+            rb.push((i, counter.clone())).unwrap();
+        }
+
+        let mut drain = rb.drain(3);
+        assert_eq!(drain.len(), 3);
+        assert_eq!(drain.next().map(|(i, _)| i), Some(0));
+        drop(drain);
+
+        // The two unyielded elements were dropped, the other five kept
+        assert_eq!(std::rc::Rc::strong_count(&counter), 6);
+        assert_eq!(rb.len(), 5);
+        let rest: Vec<_> = std::iter::from_fn(|| rb.pop()).map(|(i, _)| i).collect();
+        assert_eq!(rest, [3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_force_push_and_pop_evict() {
+        let rb = RingBuffer::new(4);
+        for i in 1..=3 {
+            assert_eq!(rb.force_push(i), None);
+        }
+
+        assert_eq!(rb.force_push(4), Some(1));
+        assert_eq!(rb.pop_evict(), Some(2));
+        assert_eq!(rb.len(), 2);
+    }
+
+    #[test]
+    fn test_map_in_place_across_wrap() {
+        let rb = RingBuffer::new(4);
+        rb.push(1).unwrap();
+        rb.push(2).unwrap();
+        rb.pop();
+        rb.push(3).unwrap();
+        rb.push(4).unwrap();
+
+        rb.map_in_place(|x| *x *= 2);
+
+        assert_eq!(rb.pop(), Some(4));
+        assert_eq!(rb.pop(), Some(6));
+        assert_eq!(rb.pop(), Some(8));
+    }
+
+    #[test]
+    fn test_promote_moves_element_to_front() {
+        let rb = RingBuffer::new(8);
+        for i in 1..=5 {
+            rb.push(i).unwrap();
+        }
+
+        rb.promote(2);
+
+        let mut out = Vec::new();
+        rb.pop_many(&mut out, 5);
+        assert_eq!(out, [3, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_grow_keeps_wrapped_elements_in_order() {
+        let mut rb = RingBuffer::new(4);
+        rb.push(0).unwrap();
+        rb.pop();
+        for i in 1..=3 {
+            rb.push(i).unwrap();
+        }
+
+        rb.grow(16);
+
+        assert_eq!(rb.capacity(), 16);
+        for i in 4..=10 {
+            rb.push(i).unwrap();
+        }
+        let all: Vec<_> = std::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(all, (1..=10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_shrink_to_keeps_room_for_live_elements() {
+        let mut rb = RingBuffer::new(64);
+        for i in 0..40 {
+            rb.push(i).unwrap();
+        }
+        while rb.len() > 5 {
+            rb.pop();
+        }
+
+        rb.shrink_to(4);
+        assert_eq!(rb.capacity(), 8);
+        rb.shrink_to(4);
+        assert_eq!(rb.capacity(), 8);
+
+        rb.pop();
+        rb.pop();
+        rb.shrink_to_fit();
+        assert_eq!(rb.capacity(), 4);
+        let all: Vec<_> = std::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(all, [37, 38, 39]);
+    }
+
+    #[test]
+    fn test_shrink_to_huge_minimum_keeps_capacity() {
+        let mut rb = RingBuffer::<u32>::new(8);
+        rb.shrink_to(usize::MAX);
+        assert_eq!(rb.capacity(), 8);
+    }
+
+    #[test]
+    fn test_shrinking_empty_buffer_keeps_a_usable_slot() {
+        let mut rb = RingBuffer::new(8);
+        rb.shrink_to_fit();
+        assert_eq!(rb.capacity(), 2);
+        rb.push(1).unwrap();
+
+        rb.pop();
+        rb.shrink_to(0);
+        assert_eq!(rb.capacity(), 2);
+        rb.push(2).unwrap();
+    }
+
+    #[test]
+    fn test_default_uses_default_capacity() {
+        #[derive(Default)]
+        struct Holder {
+            buffer: RingBuffer<u32>,
+        }
+
+        let holder = Holder::default();
+        assert_eq!(
+            holder.buffer.capacity(),
+            RingBuffer::<u32>::DEFAULT_CAPACITY
+        );
+        assert!(holder.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_push_with_skips_closure_when_full() {
+        let rb = RingBuffer::new(2);
+        assert_eq!(rb.push_with(|| [7u8; 256]), Ok(()));
+
+        let mut called = false;
+        let res = rb.push_with(|| {
+            called = true;
+            [8u8; 256]
+        });
+        assert_eq!(res, Err(()));
+        assert!(!called);
+
+        assert_eq!(rb.pop(), Some([7u8; 256]));
+        // SAFETY: The closure initializes every byte of the array
+        unsafe { rb.push_with_ptr(|slot| slot.cast::<u8>().write_bytes(9, 256)) }.unwrap();
+        assert_eq!(rb.pop(), Some([9u8; 256]));
+    }
+
+    #[test]
+    fn test_push_iter_stops_without_losing_items() {
+        let rb = RingBuffer::new(4);
+
+        let (pushed, rest) = rb.push_iter(0..10);
+        assert_eq!(pushed, 3);
+        let mut rest = rest.unwrap();
+
+        rb.pop();
+        let (pushed, rest) = rb.push_iter(&mut rest);
+        assert_eq!(pushed, 1);
+        assert!(rest.is_some());
+        assert_eq!(rest.unwrap().next(), Some(4));
+
+        rb.pop();
+        rb.pop();
+        let (pushed, rest) = rb.push_iter([20]);
+        assert_eq!(pushed, 1);
+        assert!(rest.is_none());
+        let all: Vec<_> = std::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(all, [3, 20]);
+    }
+
+    #[test]
+    fn test_zero_sized_fill_drain_wrap() {
+        let rb = RingBuffer::<()>::new(4);
+
+        for _ in 0..5 {
+            for _ in 0..3 {
+                rb.push(()).unwrap();
+            }
+            assert!(rb.is_full());
+            assert_eq!(rb.push(()), Err(()));
+
+            rb.pop().unwrap();
+            rb.push(()).unwrap(); // wraps around on later rounds
+            while rb.pop().is_some() {}
+            assert!(rb.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_zero_sized_drop_counts() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug)]
+        struct Signal;
+        impl Drop for Signal {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let rb = RingBuffer::new(8);
+        for _ in 0..10 {
+            if let Err(rejected) = rb.push(Signal) {
+                drop(rejected);
+            }
+        }
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3); // rejected pushes
+
+        drop(rb.pop());
+        drop(rb.push_overwrite(Signal)); // not full, evicts nothing
+        assert_eq!(DROPS.load(Ordering::Relaxed), 4);
+        assert_eq!(rb.len(), 7);
+
+        drop(rb);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 11);
+    }
+
+    #[test]
+    fn test_front_drops_in_place() {
+        let rb = RingBuffer::new(4);
+        let first = std::rc::Rc::new(1);
+        rb.push(first.clone()).unwrap();
+        rb.push(std::rc::Rc::new(2)).unwrap();
+
+        {
+            let front = rb.front().unwrap();
+            assert_eq!(**front, 1);
+            assert_eq!(std::rc::Rc::strong_count(&first), 2);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&first), 1);
+        assert_eq!(rb.len(), 1);
+
+        drop(rb.front());
+        assert!(rb.front().is_none());
+    }
+
+    #[test]
+    fn test_front_guard_survives_panicking_drop() {
+        #[derive(Debug)]
+        struct Bomb(std::rc::Rc<std::cell::Cell<u32>>);
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+                if self.0.get() == 1 {
+                    panic!("first drop panics");
+                }
+            }
+        }
+
+        let drops = std::rc::Rc::new(std::cell::Cell::new(0));
+        let rb = RingBuffer::new(4);
+        rb.push(Bomb(drops.clone())).unwrap();
+        rb.push(Bomb(drops.clone())).unwrap();
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(rb.front())));
+        assert!(res.is_err());
+
+        // Neither the panicked slot nor the poisoned lock gets in the way
+        assert_eq!(rb.len(), 1);
+        drop(rb);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_extract_if_keeps_rest_in_order() {
+        let rb = RingBuffer::new(8);
+        // Start mid-buffer so the run wraps
+        for i in 0..5 {
+            rb.push(i).unwrap();
+            rb.pop();
+        }
+        for i in 0..7 {
+            rb.push(i).unwrap();
+        }
+
+        assert_eq!(rb.extract_if(|v| v % 3 == 0), [0, 3, 6]);
+        assert_eq!(rb.len(), 4);
+        assert!(rb.extract_if(|_| false).is_empty());
+
+        let rest: Vec<_> = std::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(rest, [1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_panicking_destructor_drops_the_rest() {
+        #[derive(Debug)]
+        struct Fragile(u32, std::rc::Rc<std::cell::RefCell<Vec<u32>>>);
+        impl Drop for Fragile {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+                if self.0 == 2 {
+                    panic!("fragile element");
+                }
+            }
+        }
+
+        let dropped = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let fill = |rb: &RingBuffer<Fragile>| {
+            // Offset so the elements wrap around the end of the storage
+            for _ in 0..6 {
+                rb.push(Fragile(0, dropped.clone())).unwrap();
+                drop(rb.pop());
+            }
+            for i in 1..=5 {
+                rb.push(Fragile(i, dropped.clone())).unwrap();
+            }
+            dropped.borrow_mut().clear();
+        };
+
+        let rb = RingBuffer::new(8);
+        fill(&rb);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rb.clear())).is_err());
+        assert_eq!(*dropped.borrow(), [1, 2, 3, 4, 5]);
+        assert!(rb.is_empty());
+
+        fill(&rb);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(rb))).is_err());
+        assert_eq!(*dropped.borrow(), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_new_rounded() {
+        assert_eq!(RingBuffer::<u8>::new_rounded(0).capacity(), 2);
+        assert_eq!(RingBuffer::<u8>::new_rounded(5).capacity(), 8);
+        assert_eq!(RingBuffer::<u8>::new_rounded(16).capacity(), 16);
+    }
+
+    #[test]
+    fn test_try_new_rejects_bad_capacity() {
+        assert_eq!(
+            RingBuffer::<u8>::try_new(0).err(),
+            Some(CapacityError::Zero)
+        );
+        assert_eq!(
+            RingBuffer::<u8>::try_new(1).err(),
+            Some(CapacityError::SingleSlot)
+        );
+        assert_eq!(
+            RingBuffer::<u8>::try_new(12).err(),
+            Some(CapacityError::NotPowerOfTwo(12))
+        );
+        assert_eq!(RingBuffer::<u8>::try_new(16).unwrap().capacity(), 16);
+    }
+
+    #[test]
+    fn test_try_push_batch_returns_leftovers() {
+        let rb = RingBuffer::new(4);
+        rb.push(0).unwrap();
+
+        assert_eq!(rb.try_push_batch(1..6), [3, 4, 5]);
+        assert_eq!(rb.try_push_batch(6..8), [6, 7]);
+
+        rb.pop();
+        assert!(rb.try_push_batch([9]).is_empty());
+        let all: Vec<_> = std::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(all, [1, 2, 9]);
+    }
+
+    #[test]
+    fn test_retain_compacts_and_drops_rejected() {
+        let rb = RingBuffer::new(8);
+        let dropped = std::rc::Rc::new(());
+
+        // Wrap the live run around the end of the storage
+        for _ in 0..6 {
+            rb.push((0, dropped.clone())).unwrap();
+        }
+        for _ in 0..6 {
+            rb.pop();
+        }
+        for i in 0..7 {
+            rb.push((i, dropped.clone())).unwrap();
+        }
+
+        rb.retain(|(i, _)| i % 2 == 0);
+
+        assert_eq!(std::rc::Rc::strong_count(&dropped), 1 + 4);
+        assert_eq!(rb.len(), 4);
+        let kept: Vec<_> = std::iter::from_fn(|| rb.pop()).map(|(i, _)| i).collect();
+        assert_eq!(kept, [0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_sized_for_holds_exactly_len() {
+        assert_eq!(capacity_for(3), 4);
+        assert_eq!(capacity_for(4), 8);
+
+        let rb = RingBuffer::sized_for(100);
+        for i in 0..100 {
+            rb.push(i).unwrap();
+        }
+        assert_eq!(rb.len(), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "No power-of-two capacity")]
+    fn test_capacity_for_rejects_overflowing_len() {
+        capacity_for(usize::MAX / 2 + 1);
+    }
+
+    #[test]
+    fn test_sized_for_zero_still_holds_one() {
+        assert_eq!(capacity_for(0), 2);
+
+        let rb = RingBuffer::sized_for(0);
+        assert_eq!(rb.capacity(), 2);
+        rb.push(1).unwrap();
+        assert_eq!(rb.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_is_contiguous_across_wrap() {
+        let rb = RingBuffer::new(8);
+        for i in 0..6 {
+            rb.push(i).unwrap();
+        }
+        assert!(rb.is_contiguous());
+
+        for _ in 0..4 {
+            rb.pop();
+        }
+        for i in 6..10 {
+            rb.push(i).unwrap();
+        }
+        assert!(!rb.is_contiguous());
+    }
+
+    #[test]
+    fn test_debug_shows_live_elements_only() {
+        let rb = RingBuffer::new(4);
+        for i in 1..=3 {
+            rb.push(i).unwrap();
+        }
+        rb.pop();
+        rb.push(4).unwrap();
+
+        assert_eq!(
+            format!("{rb:?}"),
+            "RingBuffer { capacity: 4, len: 3, head: 0, tail: 1, elements: [2, 3, 4] }"
+        );
+    }
+
+    #[test]
+    fn test_eq_ignores_physical_offset() {
+        // Wrapped: [2, 3, 4] with tail at slot 2
+        let a = RingBuffer::new(4);
+        for i in 0..3 {
+            a.push(i).unwrap();
+        }
+        a.pop();
+        a.pop();
+        a.push(3).unwrap();
+        a.push(4).unwrap();
+
+        let b = RingBuffer::new(8);
+        for i in 2..5 {
+            b.push(i).unwrap();
+        }
+        assert_eq!(a, b);
+
+        b.push(5).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_live_elements() {
+        let rb = RingBuffer::new(4);
+        for i in 0..3 {
+            rb.push(i).unwrap();
+        }
+        rb.pop();
+        rb.push(3).unwrap();
+
+        let json = serde_json::to_string(&rb).unwrap();
+        assert_eq!(json, r#"{"capacity":4,"elements":[1,2,3]}"#);
+
+        let restored: RingBuffer<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, rb);
+        assert_eq!(restored.capacity(), 4);
+
+        let rounded: RingBuffer<i32> =
+            serde_json::from_str(r#"{"capacity":3,"elements":[1,2]}"#).unwrap();
+        assert_eq!(rounded.capacity(), 4);
+        assert_eq!(rounded.len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_rejects_unusable_capacities() {
+        let parse = |json: &str| serde_json::from_str::<RingBuffer<i32>>(json);
+
+        assert!(parse(r#"{"capacity":3,"elements":[1,2,3]}"#).is_err());
+        assert!(parse(&format!(r#"{{"capacity":{},"elements":[]}}"#, usize::MAX)).is_err());
+        assert!(parse(&format!(r#"{{"capacity":{},"elements":[]}}"#, 1usize << 62)).is_err());
+        assert!(parse(r#"{"capacity":0,"elements":[]}"#).is_err());
+        assert_eq!(
+            parse(r#"{"capacity":1,"elements":[]}"#).unwrap().capacity(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_make_contiguous_after_wrap() {
+        let mut rb = RingBuffer::new(8);
+        for i in 0..6 {
+            rb.push(i).unwrap();
+        }
+        for _ in 0..4 {
+            rb.pop();
+        }
+        for i in 6..10 {
+            rb.push(i).unwrap();
+        }
+        assert!(!rb.is_contiguous());
+
+        assert_eq!(rb.make_contiguous(), &[4, 5, 6, 7, 8, 9]);
+        assert!(rb.is_contiguous());
+        assert_eq!(rb.pop(), Some(4));
+    }
+
+    #[test]
+    fn test_as_mut_slices_split_at_wrap() {
+        let mut rb = RingBuffer::new(8);
+        for i in 0..6 {
+            rb.push(i).unwrap();
+        }
+        for _ in 0..4 {
+            rb.pop();
+        }
+        for i in 6..10 {
+            rb.push(i).unwrap();
+        }
+
+        let (front, back) = rb.as_mut_slices();
+        assert_eq!((&*front, &*back), (&[4, 5, 6, 7][..], &[8, 9][..]));
+        front.iter_mut().chain(back).for_each(|x| *x *= 10);
+
+        let drained: Vec<_> = core::iter::from_fn(|| rb.pop()).collect();
+        assert_eq!(drained, [40, 50, 60, 70, 80, 90]);
+        assert_eq!(rb.as_mut_slices(), (&mut [][..], &mut [][..]));
+    }
+
+    #[test]
+    fn test_make_contiguous_from_every_layout() {
+        for tail in 0..8 {
+            for len in 0..8 {
+                let mut rb = RingBuffer::new(8);
+                for _ in 0..tail {
+                    rb.push(0).unwrap();
+                    rb.pop();
+                }
+                for i in 0..len {
+                    rb.push(i).unwrap();
+                }
+
+                let expected: Vec<_> = (0..len).collect();
+                assert_eq!(rb.make_contiguous(), expected.as_slice());
+                assert!(rb.is_contiguous());
+
+                let drained: Vec<_> = core::iter::from_fn(|| rb.pop()).collect();
+                assert_eq!(drained, expected, "tail {tail}, len {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_len_stays_in_range_across_resizes() {
+        let rb = std::sync::Arc::new(RingBuffer::new(64));
+        for i in 0..3 {
+            rb.push(i).unwrap();
+        }
+
+        // Each grown round moves `tail` well past the next small capacity,
+        // so a reader mixing the two would wrap around
+        let resizer = {
+            let rb = rb.clone();
+            std::thread::spawn(move || {
+                for _ in 0..2000 {
+                    rb.resize(64);
+                    for i in 0..30 {
+                        rb.push(i).unwrap();
+                        rb.pop();
+                    }
+                    rb.resize(4);
+                }
+            })
+        };
+
+        while !resizer.is_finished() {
+            assert!((3..=4).contains(&rb.len()));
+            assert!(!rb.is_empty());
+        }
+        resizer.join().unwrap();
+    }
+
+    #[test]
+    fn test_resize_preserves_order() {
+        let rb = RingBuffer::new(4);
+        rb.push(1).unwrap();
+        rb.push(2).unwrap();
+        rb.pop();
+        rb.push(3).unwrap();
+        rb.push(4).unwrap();
+        assert!(rb.is_full());
+
+        rb.resize(8);
+        assert_eq!(rb.capacity(), 8);
+        assert!(!rb.is_full());
+        rb.push(5).unwrap();
+
+        for i in 2..=5 {
+            assert_eq!(rb.pop(), Some(i));
+        }
+        assert!(rb.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 2")]
+    fn test_resize_rejects_single_slot() {
+        RingBuffer::<u32>::new(4).resize(1);
+    }
+
+    #[cfg(debug_assertions)]
+    impl<T> RingBuffer<T> {
+        // Simulates an index bug by moving `head` back onto a live slot
+        fn corrupt_head(&self, index: usize) {
+            self.head.store(index, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "read while uninitialized")]
+    fn test_slot_tracking_checks_drop() {
+        let rb = RingBuffer::new(4);
+        rb.push(1).unwrap();
+
+        rb.corrupt_head(2);
+        drop(rb);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "written twice")]
+    fn test_slot_tracking_catches_double_write() {
+        let rb = RingBuffer::new(4);
+        rb.push(1).unwrap();
+        rb.push(2).unwrap();
+
+        rb.corrupt_head(0);
+        let _ = rb.push(3);
     }
 }
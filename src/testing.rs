@@ -0,0 +1,54 @@
+//! Helpers for driving channel futures by hand, so tests can assert exactly
+//! when an operation parks and when it is woken instead of relying on
+//! `yield_now` and timing. Available to downstream crates with the
+//! `testing` feature.
+//!
+//! Pair them with `Sender::waiting_senders_len` and
+//! `Receiver::waiting_receivers_len` to check that a pending future really
+//! registered its waker. `Waker::noop` covers polls whose wakeups don't
+//! matter.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+/// A waker that counts how often it, or any clone of it, was woken.
+#[derive(Debug, Clone, Default)]
+pub struct CountingWaker {
+    count: Arc<AtomicUsize>,
+}
+
+struct Counter(Arc<AtomicUsize>);
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl CountingWaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A waker that adds to this counter when woken.
+    pub fn waker(&self) -> Waker {
+        Waker::from(Arc::new(Counter(self.count.clone())))
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// Polls `fut` once with `waker`. Channel futures are `Unpin`, so a plain
+/// `&mut` to one works and it can be polled again later.
+pub fn poll_once<F: Future + Unpin>(fut: &mut F, waker: &Waker) -> Poll<F::Output> {
+    Pin::new(fut).poll(&mut Context::from_waker(waker))
+}
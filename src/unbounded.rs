@@ -0,0 +1,202 @@
+use super::{
+    channel::{SendError, WaitList, Waiter},
+    sync::lock,
+};
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+
+#[derive(Debug)]
+struct UnboundedInner<T> {
+    queue: Mutex<VecDeque<T>>,
+    waiting_receivers: WaitList,
+    sender_count: Arc<()>,
+    receiver_count: Arc<()>,
+}
+
+#[derive(Debug)]
+pub struct UnboundedSender<T> {
+    inner: Arc<UnboundedInner<T>>,
+    _sender_ref: ManuallyDrop<Arc<()>>, // clone of sender_count, released in `drop`
+}
+
+#[derive(Debug)]
+pub struct UnboundedReceiver<T> {
+    inner: Arc<UnboundedInner<T>>,
+    _receiver_ref: Arc<()>, // clone of receiver_count
+}
+
+/// Creates a channel whose `send` never waits.
+///
+/// There is no backpressure: if receivers fall behind, the queue keeps
+/// growing until the process runs out of memory. Prefer the bounded
+/// `channel` unless producers truly cannot block and their rate is known
+/// to be sustainable.
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let sender_count = Arc::new(());
+    let receiver_count = Arc::new(());
+
+    let chan = Arc::new(UnboundedInner {
+        queue: Mutex::new(VecDeque::new()),
+        waiting_receivers: WaitList::default(),
+        sender_count: sender_count.clone(),
+        receiver_count: receiver_count.clone(),
+    });
+
+    let sender = UnboundedSender {
+        inner: chan.clone(),
+        _sender_ref: ManuallyDrop::new(sender_count),
+    };
+
+    let receiver = UnboundedReceiver {
+        inner: chan,
+        _receiver_ref: receiver_count,
+    };
+
+    (sender, receiver)
+}
+
+impl<T> UnboundedInner<T> {
+    // One reference to the count token is held by `UnboundedInner` itself
+    fn sender_count(&self) -> usize {
+        Arc::strong_count(&self.sender_count) - 1
+    }
+}
+
+impl<T> UnboundedSender<T> {
+    /// Enqueues `value` immediately. Fails only once every receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if Arc::strong_count(&self.inner.receiver_count) == 1 {
+            return Err(SendError::Closed(value));
+        }
+
+        lock(&self.inner.queue).push_back(value);
+        self.inner.waiting_receivers.wake(1);
+
+        Ok(())
+    }
+}
+
+impl<T> UnboundedReceiver<T> {
+    /// The next message, waiting for one if the queue is empty. `None` once
+    /// every sender is gone and the queue is drained.
+    pub async fn recv(&self) -> Option<T> {
+        let inner = &self.inner;
+        let pop = || lock(&inner.queue).pop_front();
+
+        let mut waiter = Waiter::new(&inner.waiting_receivers);
+        let res = poll_fn(|cx| {
+            if let Some(value) = pop() {
+                return Poll::Ready(Some(value));
+            }
+            if inner.sender_count() == 0 {
+                return Poll::Ready(pop());
+            }
+
+            waiter.register(cx.waker());
+
+            // A send or the last sender's drop may have landed before we
+            // registered
+            if inner.sender_count() == 0 || !lock(&inner.queue).is_empty() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await;
+
+        waiter.deregister();
+        res
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _sender_ref: self._sender_ref.clone(),
+        }
+    }
+}
+
+impl<T> Clone for UnboundedReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _receiver_ref: self._receiver_ref.clone(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        // Release our token before counting, as in `PrioritySender::drop`
+        // SAFETY: The token is never touched again after this
+        unsafe { ManuallyDrop::drop(&mut self._sender_ref) };
+
+        if self.inner.sender_count() == 0 {
+            self.inner.waiting_receivers.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{CountingWaker, poll_once};
+
+    #[tokio::test]
+    async fn test_send_never_blocks() {
+        let (tx, rx) = unbounded_channel::<u32>();
+
+        for i in 0..1000 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+
+        for i in 0..1000 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_send_wakes_parked_receiver() {
+        let (tx, rx) = unbounded_channel::<String>();
+
+        let handle = tokio::spawn(async move { rx.recv().await });
+        tokio::task::yield_now().await;
+
+        tx.send(String::from("hello")).unwrap();
+        assert_eq!(handle.await.unwrap().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn test_dropped_recv_passes_its_wakeup_on() {
+        let (tx, rx) = unbounded_channel::<u32>();
+        let (first, second) = (CountingWaker::new(), CountingWaker::new());
+
+        let mut abandoned = Box::pin(rx.recv());
+        let mut live = Box::pin(rx.recv());
+        for _ in 0..3 {
+            assert!(poll_once(&mut abandoned, &first.waker()).is_pending());
+        }
+        assert!(poll_once(&mut live, &second.waker()).is_pending());
+
+        tx.send(1).unwrap();
+        drop(abandoned);
+        assert_eq!(second.count(), 1);
+        assert_eq!(poll_once(&mut live, &second.waker()), Poll::Ready(Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_send_fails_without_receivers() {
+        let (tx, rx) = unbounded_channel::<u32>();
+        drop(rx);
+
+        assert_eq!(tx.send(1), Err(SendError::Closed(1)));
+    }
+}
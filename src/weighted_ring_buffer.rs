@@ -0,0 +1,134 @@
+use super::{
+    ring_buffer::RingBuffer,
+    sync::{Mutex, lock},
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// How much an element counts against a `WeightedRingBuffer`'s budget,
+/// typically its size in bytes. Must not change while the element is
+/// buffered, since the same weight is taken off again when it is popped.
+pub trait Weigh {
+    fn weight(&self) -> usize;
+}
+
+impl Weigh for Vec<u8> {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weigh for Box<[u8]> {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weigh for String {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+/// A `RingBuffer` bounded by the total weight of its elements as well as
+/// by slot count, so a few huge messages can't take up memory the slot
+/// count alone would allow.
+pub struct WeightedRingBuffer<T> {
+    ring: RingBuffer<T>,
+    budget: usize,
+    // Total weight of what is queued. Held across each push and pop, so the
+    // check against `budget` and the push it admits happen as one
+    weight: Mutex<usize>,
+}
+
+impl<T: Weigh> WeightedRingBuffer<T> {
+    /// # Panics
+    /// If `capacity` is less than 2 or not a power of two.
+    pub fn new(capacity: usize, budget: usize) -> Self {
+        Self {
+            ring: RingBuffer::new(capacity),
+            budget,
+            weight: Mutex::new(0),
+        }
+    }
+
+    /// Appends `value`, handing it back if there is no free slot or its
+    /// weight would take the total past the budget. An element heavier
+    /// than the whole budget is never accepted.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut weight = lock(&self.weight);
+        let added = value.weight();
+
+        if weight
+            .checked_add(added)
+            .is_none_or(|total| total > self.budget)
+        {
+            return Err(value);
+        }
+
+        self.ring.push(value)?;
+        *weight += added;
+
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut weight = lock(&self.weight);
+        let value = self.ring.pop()?;
+        *weight -= value.weight();
+
+        Some(value)
+    }
+
+    /// Total weight of the queued elements.
+    pub fn len_bytes(&self) -> usize {
+        *lock(&self.weight)
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Whether every slot is taken. The budget may run out well before.
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_push_rejects_past_budget_or_slots() {
+        let rb = WeightedRingBuffer::new(4, 10);
+
+        rb.push(vec![0u8; 6]).unwrap();
+        assert_eq!(rb.push(vec![1; 5]), Err(vec![1; 5]));
+        rb.push(vec![2; 4]).unwrap();
+        assert_eq!(rb.len_bytes(), 10);
+
+        assert_eq!(rb.pop(), Some(vec![0; 6]));
+        assert_eq!(rb.len_bytes(), 4);
+        assert!(rb.push(vec![3; 11]).is_err());
+
+        // Three slots usable, however light the elements
+        rb.push(Vec::new()).unwrap();
+        rb.push(vec![4; 1]).unwrap();
+        assert!(rb.is_full());
+        assert_eq!(rb.push(Vec::new()), Err(Vec::new()));
+        assert_eq!(rb.len_bytes(), 5);
+    }
+}